@@ -1,15 +1,28 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use rlnc_poc::blocks::{
-    block_to_chunks, chunk_to_scalars, random_u8_slice, Committer,
+    block_to_chunks, chunk_to_scalars, random_u8_slice_from, Committer,
 };
 use rlnc_poc::node::{Message, Node, ReceiveError};
 
+// The blocks are generated from a fixed seed so every run works on the same
+// data.
+const SEED: u64 = 42;
+
+// new_committer returns a committer for chunks of chunk_size 32-byte words, each
+// scalar holds 252 bits of the chunk.
+fn new_committer(chunk_size: usize) -> Committer {
+    Committer::new((chunk_size * 32 * 8).div_ceil(252))
+}
+
 fn benchmark_commit(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(SEED);
     let chunk_size = 1;
     let num_chunks = 10;
     let block_size = chunk_size * num_chunks * 32;
-    let block: Vec<u8> = random_u8_slice(block_size);
-    let committer = Committer::new(chunk_size);
+    let block: Vec<u8> = random_u8_slice_from(block_size, &mut rng);
+    let committer = new_committer(chunk_size);
     let chunks = block_to_chunks(&block, num_chunks).unwrap();
     c.bench_function("commit small block", |b| {
         b.iter(|| {
@@ -26,9 +39,11 @@ fn benchmark_commit(c: &mut Criterion) {
     let large_chunk_size = 380;
     let large_num_chunks = 10;
     let large_block_size = large_chunk_size * large_num_chunks * 32;
-    let large_block: Vec<u8> = random_u8_slice(large_block_size);
-    let committer = Committer::new(large_chunk_size);
+    let large_block: Vec<u8> = random_u8_slice_from(large_block_size, &mut rng);
+    let committer = new_committer(large_chunk_size);
     let large_chunks = block_to_chunks(&large_block, large_num_chunks).unwrap();
+    // Only commit_vartime uses the precomputed tables, commit stays constant
+    // time.
     c.bench_function("commit large block", |b| {
         b.iter(|| {
             for chunk in &large_chunks {
@@ -40,14 +55,26 @@ fn benchmark_commit(c: &mut Criterion) {
             }
         })
     });
+    c.bench_function("commit_vartime large block", |b| {
+        b.iter(|| {
+            for chunk in &large_chunks {
+                black_box(
+                    committer
+                        .commit_vartime(&chunk_to_scalars(chunk).unwrap())
+                        .unwrap(),
+                );
+            }
+        })
+    });
 }
 
 fn benchmark_send_receive(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(SEED);
     let chunk_size = 1;
     let num_chunks = 10;
     let block_size = chunk_size * num_chunks * 32;
-    let block: Vec<u8> = random_u8_slice(block_size);
-    let committer = Committer::new(chunk_size);
+    let block: Vec<u8> = random_u8_slice_from(block_size, &mut rng);
+    let committer = new_committer(chunk_size);
     let source_node =
         rlnc_poc::node::Node::new_source(&committer, &block, num_chunks)
             .unwrap();
@@ -60,8 +87,8 @@ fn benchmark_send_receive(c: &mut Criterion) {
     let large_chunk_size = 380;
     let large_num_chunks = 10;
     let large_block_size = large_chunk_size * large_num_chunks * 32;
-    let large_block: Vec<u8> = random_u8_slice(large_block_size);
-    let committer = Committer::new(large_chunk_size);
+    let large_block: Vec<u8> = random_u8_slice_from(large_block_size, &mut rng);
+    let committer = new_committer(large_chunk_size);
     let source_node = rlnc_poc::node::Node::new_source(
         &committer,
         &large_block,
@@ -82,15 +109,13 @@ fn benchmark_send_receive(c: &mut Criterion) {
     c.bench_function("receive small block", |b| {
         b.iter(|| {
             let cloned_message = message.clone();
-            black_box(
-                destination_node
-                    .receive(cloned_message)
-                    .or_else(|e| match e {
-                        ReceiveError::LinearlyDependentChunk => Ok(()),
-                        _ => Err(e),
-                    })
-                    .unwrap(),
-            );
+            destination_node
+                .receive(black_box(cloned_message))
+                .or_else(|e| match e {
+                    ReceiveError::LinearlyDependentChunk => Ok(()),
+                    _ => Err(e),
+                })
+                .unwrap();
         })
     });
 
@@ -105,25 +130,37 @@ fn benchmark_send_receive(c: &mut Criterion) {
     c.bench_function("receive large block", |b| {
         b.iter(|| {
             let cloned_message = message.clone();
-            black_box(
-                destination_node
-                    .receive(cloned_message)
-                    .or_else(|e| match e {
-                        ReceiveError::LinearlyDependentChunk => Ok(()),
-                        _ => Err(e),
-                    })
-                    .unwrap(),
-            );
+            destination_node
+                .receive(black_box(cloned_message))
+                .or_else(|e| match e {
+                    ReceiveError::LinearlyDependentChunk => Ok(()),
+                    _ => Err(e),
+                })
+                .unwrap();
+        })
+    });
+
+    // Without the commitment verification, only the elimination is measured.
+    let messages: Vec<Message> = (0..large_num_chunks)
+        .map(|_| source_node.send().unwrap())
+        .collect();
+    c.bench_function("receive unchecked large block", |b| {
+        b.iter(|| {
+            let mut node = Node::new(&committer, large_num_chunks);
+            for message in messages.iter() {
+                let _ = node.receive_unchecked(black_box(message.clone()));
+            }
         })
     });
 }
 
 fn benchmark_decode(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(SEED);
     let chunk_size = 1;
     let num_chunks = 10;
     let block_size = chunk_size * num_chunks * 32;
-    let block: Vec<u8> = random_u8_slice(block_size);
-    let committer = Committer::new(chunk_size);
+    let block: Vec<u8> = random_u8_slice_from(block_size, &mut rng);
+    let committer = new_committer(chunk_size);
     let source_node =
         rlnc_poc::node::Node::new_source(&committer, &block, num_chunks)
             .unwrap();
@@ -146,8 +183,8 @@ fn benchmark_decode(c: &mut Criterion) {
     let large_chunk_size = 380;
     let large_num_chunks = 10;
     let large_block_size = large_chunk_size * large_num_chunks * 32;
-    let large_block: Vec<u8> = random_u8_slice(large_block_size);
-    let committer = Committer::new(large_chunk_size);
+    let large_block: Vec<u8> = random_u8_slice_from(large_block_size, &mut rng);
+    let committer = new_committer(large_chunk_size);
     let source_node = rlnc_poc::node::Node::new_source(
         &committer,
         &large_block,
@@ -172,11 +209,12 @@ fn benchmark_decode(c: &mut Criterion) {
 }
 
 fn benchmark_receive_and_decode(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(SEED);
     let chunk_size = 1;
     let num_chunks = 10;
     let block_size = chunk_size * num_chunks * 32;
-    let block: Vec<u8> = random_u8_slice(block_size);
-    let committer = Committer::new(chunk_size);
+    let block: Vec<u8> = random_u8_slice_from(block_size, &mut rng);
+    let committer = new_committer(chunk_size);
     let source_node =
         rlnc_poc::node::Node::new_source(&committer, &block, num_chunks)
             .unwrap();
@@ -203,8 +241,8 @@ fn benchmark_receive_and_decode(c: &mut Criterion) {
     let large_chunk_size = 380;
     let large_num_chunks = 10;
     let large_block_size = large_chunk_size * large_num_chunks * 32;
-    let large_block: Vec<u8> = random_u8_slice(large_block_size);
-    let committer = Committer::new(large_chunk_size);
+    let large_block: Vec<u8> = random_u8_slice_from(large_block_size, &mut rng);
+    let committer = new_committer(large_chunk_size);
     let source_node = rlnc_poc::node::Node::new_source(
         &committer,
         &large_block,
@@ -232,11 +270,47 @@ fn benchmark_receive_and_decode(c: &mut Criterion) {
     });
 }
 
+fn benchmark_decode_blocked(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let chunk_size = 4000;
+    let num_chunks = 10;
+    let block_size = chunk_size * num_chunks * 32;
+    let block: Vec<u8> = random_u8_slice_from(block_size, &mut rng);
+    let committer = new_committer(chunk_size);
+    let source_node =
+        rlnc_poc::node::Node::new_source(&committer, &block, num_chunks)
+            .unwrap();
+    let mut destination_node = Node::new(&committer, num_chunks);
+    while !destination_node.is_full() {
+        destination_node
+            .receive(source_node.send().unwrap())
+            .or_else(|e| match e {
+                ReceiveError::LinearlyDependentChunk => Ok(()),
+                _ => Err(e),
+            })
+            .unwrap();
+    }
+    c.bench_function("decode wide block", |b| {
+        b.iter(|| {
+            black_box(destination_node.decode().unwrap());
+        })
+    });
+    let mut out: Vec<u8> = Vec::with_capacity(block_size);
+    c.bench_function("decode blocked wide block", |b| {
+        b.iter(|| {
+            out.clear();
+            destination_node.decode_blocked(256, &mut out).unwrap();
+            black_box(&out);
+        })
+    });
+}
+
 criterion_group!(
     benches,
     benchmark_commit,
     benchmark_send_receive,
     benchmark_decode,
     benchmark_receive_and_decode,
+    benchmark_decode_blocked,
 );
 criterion_main!(benches);
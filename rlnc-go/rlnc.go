@@ -152,6 +152,8 @@ func (n *Node) ReceiveChunk(chunk []byte) error {
 		return fmt.Errorf("invalid message")
 	case -5:
 		return fmt.Errorf("linearly dependent chunk")
+	case -6:
+		return fmt.Errorf("hop limit exceeded")
 	default:
 		return fmt.Errorf("unknown error")
 	}
@@ -1,4 +1,13 @@
-use rlnc_poc::blocks::{random_u8_slice, Committer};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, ValueEnum};
+use rand::rngs::StdRng;
+use rand::seq::{index, SliceRandom};
+use rand::{Rng, SeedableRng};
+use rlnc_poc::blocks::{max_chunk_len, random_u8_slice_from, Committer};
 use rlnc_poc::node::{Message, Node, ReceiveError};
 
 fn main() {
@@ -8,23 +17,54 @@ fn main() {
 struct SimulationNode<'a> {
     node: Node<'a>,
     neighbors: Vec<usize>,
-    sent_message: bool,
+    // Number of messages sent, the source sends its chunks uncoded first.
+    sent_count: usize,
+    // Destinations this node still has to send to. It is refilled with the
+    // neighbors once drained, so with an upload cap the pending sends carry
+    // over to the next rounds.
+    queue: VecDeque<usize>,
 }
 struct Network<'a> {
     nodes: Vec<SimulationNode<'a>>,
     timestamp: u32,
     wasted_bandwdidth: u32,
+    // Serialized size of the linearly dependent messages.
+    wasted_bytes: u64,
     full_nodes: usize,
     round_messages: Vec<Message>,
     round_destinations: Vec<usize>,
+    // Sender of each message of the round, in the same order.
+    round_senders: Vec<usize>,
+    // Maximum number of messages a node can send in a single round.
+    upload_cap: Option<usize>,
+    // Probability that a message is lost before it is delivered.
+    loss_rate: f64,
+    // Messages lost so far, they are not counted as wasted bandwidth.
+    dropped_messages: u32,
+    // Maximum number of rounds a message can be delayed by. Each message is
+    // delayed by a number of rounds drawn uniformly up to it.
+    jitter: u32,
+    // Delayed messages with the round they arrive at and their destination.
+    in_flight: Vec<(u32, usize, Message)>,
+    // Whether the nodes recode the chunks they forward. Without coding they
+    // forward one of their chunks as received, to compare with plain
+    // forwarding.
+    coding: bool,
+    // Seeded rng for the send order and the coefficients. The nodes send in a
+    // random order each round so that lower indices have no advantage.
+    rng: StdRng,
+    send_order: Vec<usize>,
+    // Independent chunks received in the last round.
+    innovative_messages: usize,
 }
 
 impl<'a> SimulationNode<'a> {
     fn new(committer: &'a Committer, num_chunks: usize) -> Self {
         SimulationNode {
-            node: Node::<'a>::new(&committer, num_chunks),
+            node: Node::<'a>::new(committer, num_chunks),
             neighbors: Vec::new(),
-            sent_message: false,
+            sent_count: 0,
+            queue: VecDeque::new(),
         }
     }
 
@@ -33,11 +73,12 @@ impl<'a> SimulationNode<'a> {
         block: &[u8],
         num_chunks: usize,
     ) -> Result<Self, String> {
-        let node = Node::new_source(&committer, block, num_chunks)?;
+        let node = Node::new_source(committer, block, num_chunks)?;
         Ok(SimulationNode {
             node,
             neighbors: Vec::new(),
-            sent_message: false,
+            sent_count: 0,
+            queue: VecDeque::new(),
         })
     }
 }
@@ -48,43 +89,161 @@ impl<'a> Network<'a> {
         num_chunks: usize,
         mesh_size: usize,
         block: &[u8],
+        rng: &mut StdRng,
     ) -> Vec<SimulationNode<'a>> {
         let mut ret: Vec<SimulationNode> = Vec::with_capacity(num);
         let source_node =
-            SimulationNode::new_source(&committer, block, num_chunks).unwrap();
+            SimulationNode::new_source(committer, block, num_chunks).unwrap();
         ret.push(source_node);
         for _ in 1..num {
-            ret.push(SimulationNode::new(&committer, num_chunks));
+            ret.push(SimulationNode::new(committer, num_chunks));
         }
-        for i in 0..num {
-            let mut neighbors: Vec<usize> = Vec::with_capacity(mesh_size);
-            for _ in 0..mesh_size {
-                neighbors.push(rand::random::<usize>() % num);
-            }
-            ret[i].neighbors = neighbors;
+        // Each node draws distinct neighbors among the other num - 1 nodes,
+        // skipping its own index.
+        let mesh_size = mesh_size.min(num.saturating_sub(1));
+        for (i, node) in ret.iter_mut().enumerate() {
+            node.neighbors = index::sample(rng, num - 1, mesh_size)
+                .into_iter()
+                .map(|j| if j < i { j } else { j + 1 })
+                .collect();
         }
-        return ret;
+        ret
     }
     pub fn new(
         committer: &'a Committer,
         num_nodes: usize,
+        num_chunks: usize,
+        chunk_size: usize,
         mesh_size: usize,
+        upload_cap: Option<usize>,
+        seed: u64,
     ) -> Self {
-        let num_chunks = 10;
+        // The block, the topology and the coefficients are all drawn from the
+        // seeded rng so that a run can be reproduced from its seed.
+        let mut rng = StdRng::seed_from_u64(seed);
+        let block =
+            random_u8_slice_from(chunk_size * num_chunks * 32, &mut rng);
         let nodes = Network::create_nodes(
-            &committer,
-            num_nodes,
-            num_chunks,
-            mesh_size,
-            &random_u8_slice(committer.len() * num_chunks * 32),
+            committer, num_nodes, num_chunks, mesh_size, &block, &mut rng,
+        );
+        Network::with_nodes(nodes, upload_cap, rng)
+    }
+
+    // from_edge_list builds the network from a file of `src dst` node index
+    // pairs, one link per line. Links go both ways and node 0 is the source.
+    // The chunks are as large as the committer allows.
+    pub fn from_edge_list(
+        committer: &'a Committer,
+        num_chunks: usize,
+        path: &Path,
+        upload_cap: Option<usize>,
+        seed: u64,
+    ) -> io::Result<Self> {
+        let neighbors = parse_edge_list(&std::fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let block = random_u8_slice_from(
+            num_chunks * max_chunk_len(committer.len()),
+            &mut rng,
         );
+        let nodes = Network::nodes_with_neighbors(
+            committer, num_chunks, &block, neighbors,
+        )
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(Network::with_nodes(nodes, upload_cap, rng))
+    }
+
+    // new_scale_free grows the topology by preferential attachment: starting
+    // from m + 1 fully linked nodes, every new node links to m distinct nodes
+    // drawn proportionally to their degree. Links go both ways.
+    pub fn new_scale_free(
+        committer: &'a Committer,
+        num_nodes: usize,
+        num_chunks: usize,
+        chunk_size: usize,
+        m: usize,
+        upload_cap: Option<usize>,
+        seed: u64,
+    ) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let block =
+            random_u8_slice_from(chunk_size * num_chunks * 32, &mut rng);
+        let mut neighbors: Vec<Vec<usize>> = vec![Vec::new(); num_nodes];
+        // Every link adds both its ends, so drawing from it is proportional
+        // to the degree.
+        let mut ends: Vec<usize> = Vec::new();
+        let initial = num_nodes.min(m + 1);
+        for i in 0..initial {
+            for j in 0..i {
+                neighbors[i].push(j);
+                neighbors[j].push(i);
+                ends.extend([i, j]);
+            }
+        }
+        for i in initial..num_nodes {
+            let mut targets: Vec<usize> = Vec::with_capacity(m);
+            while targets.len() < m {
+                let j = ends[rng.gen_range(0..ends.len())];
+                if !targets.contains(&j) {
+                    targets.push(j);
+                }
+            }
+            for j in targets {
+                neighbors[i].push(j);
+                neighbors[j].push(i);
+                ends.extend([i, j]);
+            }
+        }
+        let nodes = Network::nodes_with_neighbors(
+            committer, num_chunks, &block, neighbors,
+        )
+        .unwrap();
+        Network::with_nodes(nodes, upload_cap, rng)
+    }
+
+    // nodes_with_neighbors creates the source, as node 0, and the other nodes
+    // with the given neighbors.
+    fn nodes_with_neighbors(
+        committer: &'a Committer,
+        num_chunks: usize,
+        block: &[u8],
+        neighbors: Vec<Vec<usize>>,
+    ) -> Result<Vec<SimulationNode<'a>>, String> {
+        let mut nodes = Vec::with_capacity(neighbors.len());
+        nodes.push(SimulationNode::new_source(committer, block, num_chunks)?);
+        for _ in 1..neighbors.len() {
+            nodes.push(SimulationNode::new(committer, num_chunks));
+        }
+        for (node, neighbors) in nodes.iter_mut().zip(neighbors) {
+            node.neighbors = neighbors;
+        }
+        Ok(nodes)
+    }
+
+    fn with_nodes(
+        nodes: Vec<SimulationNode<'a>>,
+        upload_cap: Option<usize>,
+        rng: StdRng,
+    ) -> Self {
+        let num_nodes = nodes.len();
         Network {
             nodes,
             timestamp: 0,
             wasted_bandwdidth: 0,
+            wasted_bytes: 0,
             full_nodes: 1,
             round_destinations: Vec::new(),
             round_messages: Vec::new(),
+            round_senders: Vec::new(),
+            upload_cap,
+            rng,
+            send_order: (0..num_nodes).collect(),
+            innovative_messages: 0,
+            loss_rate: 0.0,
+            dropped_messages: 0,
+            jitter: 0,
+            in_flight: Vec::new(),
+            coding: true,
         }
     }
 
@@ -92,38 +251,129 @@ impl<'a> Network<'a> {
         self.timestamp += 1;
         self.round_messages.clear();
         self.round_destinations.clear();
-        for i in 0..self.nodes.len() {
+        self.round_senders.clear();
+        self.innovative_messages = 0;
+        self.send_order.shuffle(&mut self.rng);
+        // Nodes keep sending every round, but only to the neighbors that were
+        // not full when the round started.
+        let full: Vec<bool> =
+            self.nodes.iter().map(|n| n.node.is_full()).collect();
+        for &i in self.send_order.iter() {
             let source = &mut self.nodes[i];
-            for &j in source.neighbors.iter() {
-                if j == i {
+            if source.queue.is_empty() {
+                source
+                    .queue
+                    .extend(source.neighbors.iter().filter(|&&j| !full[j]));
+            }
+            let cap = self.upload_cap.unwrap_or(source.queue.len());
+            let mut sent = 0;
+            while sent < cap {
+                let Some(&j) = source.queue.front() else {
+                    break;
+                };
+                if full[j] {
+                    source.queue.pop_front();
                     continue;
                 }
-                if let Ok(message) = source.node.send() {
-                    source.sent_message = true;
-                    self.round_messages.push(message);
-                    self.round_destinations.push(j);
+                // The source sends its chunks uncoded before coding them.
+                let message = if i == 0
+                    && source.sent_count < source.node.chunks().len()
+                {
+                    source.node.send_systematic(source.sent_count)
+                } else if !self.coding {
+                    forward_uncoded(&source.node, &mut self.rng)
+                } else {
+                    source.node.send_with_rng(&mut self.rng)
+                };
+                let Ok(message) = message else {
+                    break;
+                };
+                source.queue.pop_front();
+                source.sent_count += 1;
+                self.round_messages.push(message);
+                self.round_destinations.push(j);
+                self.round_senders.push(i);
+                sent += 1;
+            }
+        }
+        // The messages delayed in the previous rounds arrive first.
+        let (due, in_flight) = std::mem::take(&mut self.in_flight)
+            .into_iter()
+            .partition(|&(t, _, _)| t <= self.timestamp);
+        self.in_flight = in_flight;
+        for (_, j, message) in due {
+            self.deliver(j, &message);
+        }
+        let messages = std::mem::take(&mut self.round_messages);
+        let destinations = std::mem::take(&mut self.round_destinations);
+        for (message, &j) in messages.iter().zip(destinations.iter()) {
+            // Only draw when there is loss or jitter, so that the other runs
+            // draw the same coefficients whatever these options.
+            if self.loss_rate > 0.0 && self.rng.gen_bool(self.loss_rate) {
+                self.dropped_messages += 1;
+                continue;
+            }
+            let delay = match self.jitter {
+                0 => 0,
+                jitter => self.rng.gen_range(0..=jitter),
+            };
+            if delay > 0 {
+                self.in_flight.push((
+                    self.timestamp + delay,
+                    j,
+                    message.clone(),
+                ));
+            } else {
+                self.deliver(j, message);
+            }
+        }
+        self.round_messages = messages;
+        self.round_destinations = destinations;
+    }
+
+    fn deliver(&mut self, j: usize, message: &Message) {
+        let destination = &mut self.nodes[j];
+        match destination.node.receive(message.clone()) {
+            Ok(_) => {
+                self.innovative_messages += 1;
+                if destination.node.is_full() {
+                    self.full_nodes += 1;
                 }
             }
+            Err(ReceiveError::LinearlyDependentChunk) => {
+                self.wasted_bandwdidth += 1;
+                self.wasted_bytes += message.serialized_len() as u64;
+            }
+            Err(e) => {
+                panic!("Unhandled error: {:?}", e);
+            }
         }
-        self.round_messages
+    }
+
+    // max_coefficient_bits returns the bit length of the largest coefficient
+    // stored in the network, to track how relaying makes them grow.
+    pub fn max_coefficient_bits(&self) -> f64 {
+        self.nodes
             .iter()
-            .zip(self.round_destinations.iter())
-            .for_each(|(message, &j)| {
-                let destination = &mut self.nodes[j];
-                match destination.node.receive(message.clone()) {
-                    Ok(_) => {
-                        if destination.node.is_full() {
-                            self.full_nodes += 1;
-                        }
-                    }
-                    Err(ReceiveError::LinearlyDependentChunk) => {
-                        self.wasted_bandwdidth += 1;
-                    }
-                    Err(e) => {
-                        panic!("Unhandled error: {:?}", e);
-                    }
-                }
-            });
+            .map(|n| n.node.coefficient_stats().max_magnitude)
+            .fold(0.0, f64::max)
+            .log2()
+            .max(0.0)
+    }
+
+    // eta_rounds estimates the rounds left until every node is full, assuming
+    // the innovative chunks of the last round spread evenly among the nodes
+    // that are not full yet.
+    pub fn eta_rounds(&self) -> f64 {
+        let missing = self.nodes.len() - self.full_nodes;
+        if missing == 0 {
+            return 0.0;
+        }
+        let rate = self.innovative_messages as f64 / missing as f64;
+        self.nodes
+            .iter()
+            .map(|n| n.node.eta_rounds(rate))
+            .fold(0.0, f64::max)
     }
 
     pub fn all_nodes_full(&self) -> bool {
@@ -131,17 +381,554 @@ impl<'a> Network<'a> {
     }
 }
 
+// parse_edge_list returns the neighbors of each node from `src dst` lines.
+// There are as many nodes as the highest index plus one, and each of them
+// must have at least one link.
+fn parse_edge_list(contents: &str) -> Result<Vec<Vec<usize>>, String> {
+    let mut edges = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let indices: Vec<usize> = line
+            .split_whitespace()
+            .map(|x| x.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Line {}: {}", i + 1, e))?;
+        let [src, dst] = indices[..] else {
+            return Err(format!("Line {}: expected two node indices", i + 1));
+        };
+        if src == dst {
+            return Err(format!(
+                "Line {}: node {} links to itself",
+                i + 1,
+                src
+            ));
+        }
+        edges.push((src, dst));
+    }
+    let num_nodes = edges
+        .iter()
+        .map(|&(src, dst)| src.max(dst) + 1)
+        .max()
+        .ok_or("The edge list is empty")?;
+    let mut neighbors = vec![Vec::new(); num_nodes];
+    for (src, dst) in edges {
+        if !neighbors[src].contains(&dst) {
+            neighbors[src].push(dst);
+            neighbors[dst].push(src);
+        }
+    }
+    if let Some(i) = neighbors.iter().position(|n| n.is_empty()) {
+        return Err(format!(
+            "Node {} has no links, the indices must be contiguous",
+            i
+        ));
+    }
+    Ok(neighbors)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Topology {
+    /// Every node picks --mesh random neighbors.
+    Uniform,
+    /// Preferential attachment with --mesh links per new node.
+    ScaleFree,
+}
+
+#[derive(Parser)]
+#[command(about = "Simulates RLNC block propagation on a random topology")]
+struct Args {
+    /// Number of nodes in the network, similar to Ethereum mainnet.
+    #[arg(long, default_value_t = 10000)]
+    nodes: usize,
+    /// Number of chunks the block is split into.
+    #[arg(long, default_value_t = 10)]
+    chunks: usize,
+    /// Size of each chunk in 32-byte words.
+    #[arg(long, default_value_t = 1)]
+    chunk_size: usize,
+    /// Number of neighbors of each node.
+    #[arg(long, default_value_t = 10)]
+    mesh: usize,
+    /// Stop after this many rounds even if some nodes are not full.
+    #[arg(long, default_value_t = 100)]
+    max_rounds: u32,
+    /// Seed of the simulation rng, drawn at random if missing.
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Maximum number of messages a node can send in a single round.
+    #[arg(long)]
+    upload_cap: Option<usize>,
+    /// Probability that each message is lost, between 0 and 1.
+    #[arg(long, default_value_t = 0.0, value_parser = parse_probability)]
+    loss: f64,
+    /// Maximum number of rounds each message can be delayed by, drawn
+    /// uniformly for every message.
+    #[arg(long, default_value_t = 0)]
+    jitter: u32,
+    /// How the topology is drawn.
+    #[arg(long, value_enum, default_value_t = Topology::Uniform)]
+    topology: Topology,
+    /// Read the topology from a file of `src dst` links instead of drawing
+    /// it, --nodes and --mesh are then ignored.
+    #[arg(long, conflicts_with = "topology")]
+    edge_list: Option<PathBuf>,
+    /// Forward the received chunks without recoding them, to compare the
+    /// time to full with plain forwarding.
+    #[arg(long)]
+    no_coding: bool,
+    /// Write the metrics of every round to this CSV file.
+    #[arg(long)]
+    csv: Option<PathBuf>,
+}
+
+// forward_uncoded sends one of the chunks stored by the node, picked at
+// random. As long as every node forwards uncoded, the stored chunks are all
+// source chunks.
+fn forward_uncoded<R: Rng>(
+    node: &Node,
+    rng: &mut R,
+) -> Result<Message, String> {
+    let num_chunks = node.chunks().len();
+    if num_chunks == 0 {
+        return Err("The node has no chunks to forward".to_string());
+    }
+    let mut scalars = vec![0u8; num_chunks];
+    scalars[rng.gen_range(0..num_chunks)] = 1;
+    node.send_with_coeffs(&scalars)
+}
+
+fn parse_probability(value: &str) -> Result<f64, String> {
+    let p: f64 = value.parse().map_err(|e| format!("{}", e))?;
+    if !(0.0..=1.0).contains(&p) {
+        return Err(format!("{} is not between 0 and 1", p));
+    }
+    Ok(p)
+}
+
 fn run_simulation() {
-    let num_nodes = 10000; // Similar to Ethereum mainnet
-    let chunk_size = 1;
-    let committer = Committer::new(chunk_size);
-    let mesh_size = 10;
-    let mut network = Network::new(&committer, num_nodes, mesh_size);
-    while !network.all_nodes_full() && network.timestamp < 100 {
+    let args = Args::parse();
+    let seed = args.seed.unwrap_or_else(rand::random);
+    println!(
+        "Nodes: {}, Chunks: {}, Chunk size: {}, Mesh: {}, Topology: {:?}, Max rounds: {}, Upload cap: {:?}, Loss: {}, Jitter: {}, Coding: {}, Seed: {}",
+        args.nodes,
+        args.chunks,
+        args.chunk_size,
+        args.mesh,
+        args.topology,
+        args.max_rounds,
+        args.upload_cap,
+        args.loss,
+        args.jitter,
+        !args.no_coding,
+        seed
+    );
+    // Each scalar holds 252 bits of the chunk, round up to fit it entirely.
+    let committer = Committer::new((args.chunk_size * 32 * 8).div_ceil(252));
+    let mut network = match args.edge_list {
+        Some(path) => Network::from_edge_list(
+            &committer,
+            args.chunks,
+            &path,
+            args.upload_cap,
+            seed,
+        )
+        .expect("Failed to load the edge list"),
+        None if args.topology == Topology::ScaleFree => {
+            Network::new_scale_free(
+                &committer,
+                args.nodes,
+                args.chunks,
+                args.chunk_size,
+                args.mesh,
+                args.upload_cap,
+                seed,
+            )
+        }
+        None => Network::new(
+            &committer,
+            args.nodes,
+            args.chunks,
+            args.chunk_size,
+            args.mesh,
+            args.upload_cap,
+            seed,
+        ),
+    };
+    network.loss_rate = args.loss;
+    network.jitter = args.jitter;
+    network.coding = !args.no_coding;
+    let csv = args.csv.map(|path| {
+        BufWriter::new(File::create(path).expect("Failed to create the CSV"))
+    });
+    run_rounds(&mut network, args.max_rounds, csv)
+        .expect("Failed to write the CSV");
+}
+
+// run_rounds runs the network until every node is full or max_rounds is
+// reached, printing the metrics of each round and writing them to csv if set.
+fn run_rounds<W: Write>(
+    network: &mut Network,
+    max_rounds: u32,
+    mut csv: Option<W>,
+) -> io::Result<()> {
+    if let Some(csv) = csv.as_mut() {
+        writeln!(csv, "timestamp,full_nodes,wasted_bandwidth,wasted_bytes")?;
+    }
+    while !network.all_nodes_full() && network.timestamp < max_rounds {
         network.round();
         println!(
-            "Timestamp: {}, Full nodes: {}, Wasted Bandwidth: {}",
-            network.timestamp, network.full_nodes, network.wasted_bandwdidth
+            "Timestamp: {}, Full nodes: {}, Wasted Bandwidth: {} messages ({} bytes), Dropped: {}, Max coefficient bits: {:.1}, ETA: {:.1} rounds",
+            network.timestamp,
+            network.full_nodes,
+            network.wasted_bandwdidth,
+            network.wasted_bytes,
+            network.dropped_messages,
+            network.max_coefficient_bits(),
+            network.eta_rounds()
         );
+        if let Some(csv) = csv.as_mut() {
+            writeln!(
+                csv,
+                "{},{},{},{}",
+                network.timestamp,
+                network.full_nodes,
+                network.wasted_bandwdidth,
+                network.wasted_bytes
+            )?;
+        }
+    }
+    if let Some(csv) = csv.as_mut() {
+        csv.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    use rlnc_poc::blocks::random_u8_slice;
+
+    // Chunk size of the test networks, in 32-byte words.
+    const CHUNK_SIZE: usize = 1;
+
+    fn test_committer() -> Committer {
+        Committer::new((CHUNK_SIZE * 32 * 8).div_ceil(252))
+    }
+
+    #[test]
+    fn test_args() {
+        let args = Args::parse_from(["random_topology"]);
+        assert_eq!(args.nodes, 10000);
+        assert_eq!(args.chunks, 10);
+        assert_eq!(args.seed, None);
+
+        let args = Args::parse_from([
+            "random_topology",
+            "--nodes",
+            "50",
+            "--chunk-size",
+            "2",
+            "--max-rounds",
+            "5",
+            "--seed",
+            "3",
+        ]);
+        assert_eq!(args.nodes, 50);
+        assert_eq!(args.chunk_size, 2);
+        assert_eq!(args.max_rounds, 5);
+        assert_eq!(args.seed, Some(3));
+
+        // The edge list replaces the drawn topology.
+        assert!(Args::try_parse_from([
+            "random_topology",
+            "--edge-list",
+            "links.txt",
+            "--topology",
+            "scale-free",
+        ])
+        .is_err());
+        let args = Args::parse_from(["random_topology", "--no-coding"]);
+        assert!(args.no_coding);
+
+        // Every option is described in the help.
+        let command = Args::command();
+        assert!(command
+            .get_arguments()
+            .filter(|arg| arg.get_long().is_some_and(|long| long != "help"))
+            .all(|arg| arg.get_help().is_some()));
+    }
+
+    #[test]
+    fn test_csv() {
+        let committer = test_committer();
+        let mut network =
+            Network::new(&committer, 10, 4, CHUNK_SIZE, 3, None, 5);
+        let mut csv = Vec::new();
+        run_rounds(&mut network, 3, Some(&mut csv)).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "timestamp,full_nodes,wasted_bandwidth,wasted_bytes"
+        );
+        assert_eq!(lines.len() - 1, network.timestamp as usize);
+        assert!(lines[1].starts_with("1,"));
+        assert!(lines[1..].iter().all(|l| l.split(',').count() == 4));
+    }
+
+    #[test]
+    fn test_edge_list() {
+        let committer = test_committer();
+        let path = std::env::temp_dir()
+            .join(format!("rlnc_edge_list_{}", std::process::id()));
+        std::fs::write(&path, "0 1\n1 2\n\n2 0\n1 0\n").unwrap();
+        let network =
+            Network::from_edge_list(&committer, 4, &path, None, 0).unwrap();
+        let mut neighbors: Vec<Vec<usize>> =
+            network.nodes.iter().map(|n| n.neighbors.clone()).collect();
+        neighbors.iter_mut().for_each(|n| n.sort());
+        assert_eq!(neighbors, vec![vec![1, 2], vec![0, 2], vec![0, 1]]);
+        assert!(network.nodes[0].node.is_full());
+        assert_eq!(network.nodes[0].node.chunks().len(), 4);
+        assert!(!network.nodes[1].node.is_full());
+
+        std::fs::write(&path, "0 1\n1 3\n").unwrap();
+        assert!(Network::from_edge_list(&committer, 4, &path, None, 0).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(parse_edge_list("0 1\n1 1\n").is_err());
+        assert!(parse_edge_list("0 1 2\n").is_err());
+        assert!(parse_edge_list("0 -1\n").is_err());
+        assert!(parse_edge_list("").is_err());
+    }
+
+    #[test]
+    fn test_scale_free() {
+        let committer = test_committer();
+        // The ratio between the highest and the mean degree, counting the
+        // links in both directions.
+        let skew = |network: &Network| {
+            let mut degrees = vec![0usize; network.nodes.len()];
+            for (i, node) in network.nodes.iter().enumerate() {
+                for &j in node.neighbors.iter() {
+                    degrees[i] += 1;
+                    degrees[j] += 1;
+                }
+            }
+            let mean =
+                degrees.iter().sum::<usize>() as f64 / degrees.len() as f64;
+            *degrees.iter().max().unwrap() as f64 / mean
+        };
+        let scale_free =
+            Network::new_scale_free(&committer, 500, 4, CHUNK_SIZE, 3, None, 1);
+        assert_eq!(scale_free.nodes.len(), 500);
+        for (i, node) in scale_free.nodes.iter().enumerate() {
+            assert!(node.neighbors.len() >= 3);
+            assert!(!node.neighbors.contains(&i));
+            assert!(node
+                .neighbors
+                .iter()
+                .all(|&j| scale_free.nodes[j].neighbors.contains(&i)));
+        }
+        let uniform = Network::new(&committer, 500, 4, CHUNK_SIZE, 3, None, 1);
+        assert!(
+            skew(&scale_free) > 2.0 * skew(&uniform),
+            "{} {}",
+            skew(&scale_free),
+            skew(&uniform)
+        );
+    }
+
+    #[test]
+    fn test_distinct_neighbors() {
+        let committer = test_committer();
+        let network = Network::new(&committer, 30, 4, CHUNK_SIZE, 8, None, 3);
+        for (i, node) in network.nodes.iter().enumerate() {
+            let mut neighbors = node.neighbors.clone();
+            assert_eq!(neighbors.len(), 8);
+            assert!(!neighbors.contains(&i));
+            neighbors.sort();
+            neighbors.dedup();
+            assert_eq!(neighbors.len(), 8);
+        }
+
+        // The mesh is capped by the number of other nodes.
+        let network = Network::new(&committer, 4, 4, CHUNK_SIZE, 8, None, 3);
+        for (i, node) in network.nodes.iter().enumerate() {
+            let mut neighbors = node.neighbors.clone();
+            neighbors.sort();
+            let expected: Vec<usize> = (0..4).filter(|&j| j != i).collect();
+            assert_eq!(neighbors, expected);
+        }
+    }
+
+    #[test]
+    fn test_line_graph() {
+        let committer = test_committer();
+        let num_chunks = 4;
+        let block = random_u8_slice(num_chunks * 32);
+        let neighbors = vec![vec![1], vec![0, 2], vec![1, 3], vec![2]];
+        let nodes = Network::nodes_with_neighbors(
+            &committer, num_chunks, &block, neighbors,
+        )
+        .unwrap();
+        let mut network =
+            Network::with_nodes(nodes, Some(1), StdRng::seed_from_u64(0));
+        while !network.all_nodes_full() && network.timestamp < 40 {
+            network.round();
+        }
+        assert!(network.nodes[3].node.is_full());
+        assert_eq!(network.nodes[3].node.decode().unwrap(), block);
+
+        // Once every node is full there is nobody left to send to.
+        network.round();
+        assert!(network.round_messages.is_empty());
+    }
+
+    #[test]
+    fn test_loss() {
+        let committer = test_committer();
+        let mut network =
+            Network::new(&committer, 10, 4, CHUNK_SIZE, 3, None, 2);
+        network.loss_rate = 1.0;
+        for _ in 0..5 {
+            network.round();
+            assert!(!network.round_messages.is_empty());
+        }
+        assert_eq!(network.full_nodes, 1);
+        assert!(network.nodes[1..].iter().all(|n| n.node.rank() == 0));
+        assert_eq!(network.wasted_bandwdidth, 0);
+        assert_eq!(
+            network.dropped_messages as usize,
+            5 * network.round_messages.len()
+        );
+
+        // Under loss, recoding fills the network faster than forwarding the
+        // chunks as received.
+        let rounds_to_full = |coding: bool| {
+            let mut network =
+                Network::new(&committer, 10, 4, CHUNK_SIZE, 3, Some(1), 2);
+            network.loss_rate = 0.3;
+            network.coding = coding;
+            while !network.all_nodes_full() && network.timestamp < 100 {
+                network.round();
+            }
+            assert!(network.all_nodes_full());
+            network.timestamp
+        };
+        assert!(rounds_to_full(true) < rounds_to_full(false));
+
+        assert!(parse_probability("0.25").is_ok());
+        assert!(parse_probability("1.5").is_err());
+        assert!(parse_probability("-0.1").is_err());
+    }
+
+    #[test]
+    fn test_jitter() {
+        let committer = test_committer();
+        let mut network =
+            Network::new(&committer, 10, 4, CHUNK_SIZE, 3, None, 4);
+        network.jitter = 2;
+        network.round();
+        // Every message is either delivered or still in flight.
+        let delivered =
+            network.innovative_messages + network.wasted_bandwdidth as usize;
+        assert!(!network.in_flight.is_empty());
+        assert_eq!(
+            delivered + network.in_flight.len(),
+            network.round_messages.len()
+        );
+        assert!(network
+            .in_flight
+            .iter()
+            .all(|&(t, _, _)| t > 1 && t <= 1 + network.jitter));
+
+        while !network.all_nodes_full() && network.timestamp < 100 {
+            network.round();
+        }
+        assert!(network.all_nodes_full());
+        assert_eq!(network.dropped_messages, 0);
+    }
+
+    #[test]
+    fn test_upload_cap() {
+        let committer = test_committer();
+        let mut network =
+            Network::new(&committer, 50, 10, CHUNK_SIZE, 8, Some(1), 0);
+
+        // Only the source has chunks in the first round, it has 8 neighbors
+        // but can only send one message.
+        network.round();
+        assert_eq!(network.round_messages.len(), 1);
+        assert_eq!(network.round_messages[0].is_systematic(), Some(0));
+
+        for _ in 0..5 {
+            network.round();
+            let mut sent = vec![0; network.nodes.len()];
+            for &i in network.round_senders.iter() {
+                sent[i] += 1;
+            }
+            assert!(sent.iter().all(|&count| count <= 1));
+            assert_eq!(
+                network.round_senders.len(),
+                network.round_messages.len()
+            );
+        }
+    }
+
+    #[test]
+    fn test_seeded_metrics() {
+        let committer = test_committer();
+        let mut network =
+            Network::new(&committer, 20, 10, CHUNK_SIZE, 4, None, 42);
+        let mut other =
+            Network::new(&committer, 20, 10, CHUNK_SIZE, 4, None, 42);
+        for _ in 0..3 {
+            network.round();
+            other.round();
+            assert_eq!(network.full_nodes, other.full_nodes);
+            assert_eq!(network.wasted_bandwdidth, other.wasted_bandwdidth);
+            assert_eq!(network.innovative_messages, other.innovative_messages);
+        }
+        let neighbors = |n: &Network| -> Vec<Vec<usize>> {
+            n.nodes.iter().map(|n| n.neighbors.clone()).collect()
+        };
+        assert_eq!(neighbors(&network), neighbors(&other));
+
+        let third = Network::new(&committer, 20, 10, CHUNK_SIZE, 4, None, 43);
+        assert_ne!(neighbors(&network), neighbors(&third));
+    }
+
+    #[test]
+    fn test_send_order() {
+        let committer = test_committer();
+        let mut network =
+            Network::new(&committer, 20, 10, CHUNK_SIZE, 4, None, 7);
+        let mut other =
+            Network::new(&committer, 20, 10, CHUNK_SIZE, 4, None, 7);
+        let mut orders = Vec::new();
+        for _ in 0..3 {
+            network.round();
+            other.round();
+            assert_eq!(network.send_order, other.send_order);
+            orders.push(network.send_order.clone());
+
+            // The nodes sent in the shuffled order.
+            let mut position = vec![0; network.send_order.len()];
+            for (k, &i) in network.send_order.iter().enumerate() {
+                position[i] = k;
+            }
+            assert!(!network.round_senders.is_empty());
+            assert!(network
+                .round_senders
+                .windows(2)
+                .all(|w| position[w[0]] <= position[w[1]]));
+        }
+        assert_ne!(orders[0], orders[1]);
+        assert_ne!(orders[1], orders[2]);
     }
 }
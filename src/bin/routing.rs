@@ -28,7 +28,7 @@ impl Network {
             });
         }
         ret[0].full = true;
-        return ret;
+        ret
     }
 
     pub fn new(num_nodes: usize, mesh_size: usize) -> Self {
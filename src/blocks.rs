@@ -1,61 +1,482 @@
-use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
-use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::ristretto::{
+    RistrettoPoint, VartimeRistrettoPrecomputation,
+};
 use curve25519_dalek::scalar::Scalar;
-use curve25519_dalek::traits::MultiscalarMul;
-use rand::Rng;
+use curve25519_dalek::traits::{
+    Identity, MultiscalarMul, VartimeMultiscalarMul,
+    VartimePrecomputedMultiscalarMul,
+};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::hash;
+use crate::node::Message;
+
+// BlockError is returned when a block, chunk or scalar vector can't be
+// converted or committed to, or fails validate_block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockError {
+    ZeroChunks,
+    NotDivisibleByNumChunks {
+        block_len: usize,
+        num_chunks: usize,
+    },
+    NotDivisibleBy32 {
+        chunk_len: usize,
+    },
+    // A chunk needs more scalars than the committer has generators.
+    ChunkTooLarge {
+        expected: usize,
+        got: usize,
+    },
+    // ChunkTooLarge for the chunk at the given index of a batch.
+    BatchChunkTooLarge {
+        chunk: usize,
+        expected: usize,
+        got: usize,
+    },
+    // The first chunk passed to concat_chunk does not span the committer.
+    ConcatChunkLen {
+        expected: usize,
+        got: usize,
+    },
+    // The chunk and column index the chunk and the 32-byte word within it.
+    NonCanonicalScalar {
+        chunk: usize,
+        column: usize,
+    },
+    // A group of packed scalars without room for the tail bits.
+    InvalidScalarChunk {
+        len: usize,
+    },
+    // A scalar of packed bytes with more than 252 bits.
+    ScalarTooWide {
+        index: usize,
+    },
+    // A padded block shorter than its length header.
+    PaddedBlockTooShort {
+        len: usize,
+    },
+    // The length header of a padded block exceeds the bytes that follow it.
+    PaddedLenTooLarge {
+        len: usize,
+        available: usize,
+    },
+    // commit_hiding on a committer created without new_hiding.
+    NoBlindingGenerator,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::ZeroChunks => {
+                write!(f, "The number of chunks must be positive")
+            }
+            BlockError::NotDivisibleByNumChunks {
+                block_len,
+                num_chunks,
+            } => write!(
+                f,
+                "Block size {} is not divisible by num_chunks {}",
+                block_len, num_chunks
+            ),
+            BlockError::NotDivisibleBy32 { chunk_len } => {
+                write!(f, "Chunk size {} is not divisible by 32", chunk_len)
+            }
+            BlockError::ChunkTooLarge { expected, got } => write!(
+                f,
+                "Chunk size is too large. Expected {}, got {}",
+                expected, got
+            ),
+            BlockError::BatchChunkTooLarge {
+                chunk,
+                expected,
+                got,
+            } => write!(
+                f,
+                "Chunk {} is too large. Expected {}, got {}",
+                chunk, expected, got
+            ),
+            BlockError::ConcatChunkLen { expected, got } => write!(
+                f,
+                "The first chunk must have {} scalars, got {}",
+                expected, got
+            ),
+            BlockError::InvalidScalarChunk { len } => write!(
+                f,
+                "Invalid scalar chunk size {}: each chunk must have enough scalars to contain data and tail bits",
+                len
+            ),
+            BlockError::NonCanonicalScalar { chunk, column } => write!(
+                f,
+                "Word {} of chunk {} is not a canonical scalar",
+                column, chunk
+            ),
+            BlockError::ScalarTooWide { index } => {
+                write!(f, "Scalar {} has more than 252 bits", index)
+            }
+            BlockError::PaddedBlockTooShort { len } => {
+                write!(f, "The padded block is too short: {} bytes", len)
+            }
+            BlockError::PaddedLenTooLarge { len, available } => write!(
+                f,
+                "The block length {} exceeds the padded block of {} bytes",
+                len, available
+            ),
+            BlockError::NoBlindingGenerator => {
+                write!(f, "The committer has no blinding generator")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BlockError {}
+
+// The rest of the crate reports errors as strings.
+impl From<BlockError> for String {
+    fn from(e: BlockError) -> String {
+        e.to_string()
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct Committer {
     generators: Vec<RistrettoPoint>,
+    // Extra generator multiplied by the blinding factor of hiding
+    // commitments, see new_hiding.
+    blinding_generator: Option<RistrettoPoint>,
+    // Precomputed tables for the generators, built on the first
+    // commit_vartime that spans all of them and dropped by clear_caches.
+    #[serde(skip)]
+    precomputation: Mutex<Option<Arc<VartimeRistrettoPrecomputation>>>,
+    // Number of times the precomputation was built.
+    #[serde(skip)]
+    precomputation_builds: AtomicUsize,
 }
 
 impl Committer {
+    // new returns a committer with n generators hashed to the curve from a
+    // random seed, see from_seed.
     pub fn new(n: usize) -> Self {
+        Committer::from_seed(rand::thread_rng().gen(), n)
+    }
+
+    // from_seed derives the generators by hashing the seed and their index to
+    // the curve, so committers built from the same seed are identical and
+    // nobody knows the discrete logs between the generators. It panics if n
+    // is zero, see from_generators.
+    pub fn from_seed(seed: [u8; 32], n: usize) -> Self {
+        Committer::from_generators(generators_from_seed(&seed, n))
+            .expect("The seeded generators are invalid")
+    }
+
+    // new_hiding returns a committer like new with an additional generator
+    // for the blinding factor of commit_hiding.
+    pub fn new_hiding(n: usize) -> Self {
+        let mut generators =
+            generators_from_seed(&rand::thread_rng().gen(), n + 1);
+        let blinding_generator = generators.pop();
+        Committer {
+            blinding_generator,
+            ..Committer::with_generators(generators)
+        }
+    }
+
+    // from_generators builds a committer from generators obtained elsewhere,
+    // for instance from a trusted setup. They must be distinct and not the
+    // identity, see check_binding.
+    pub fn from_generators(
+        generators: Vec<RistrettoPoint>,
+    ) -> Result<Self, String> {
+        if generators.is_empty() {
+            return Err("The generators are empty".to_string());
+        }
+        let committer = Committer::with_generators(generators);
+        committer.check_binding()?;
+        Ok(committer)
+    }
+
+    fn with_generators(generators: Vec<RistrettoPoint>) -> Self {
         Committer {
-            generators: generators(n),
+            generators,
+            blinding_generator: None,
+            precomputation: Mutex::new(None),
+            precomputation_builds: AtomicUsize::new(0),
         }
     }
 
+    // from_bytes deserializes a committer and checks its generators, see
+    // check_binding.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let committer: Committer =
+            bincode::deserialize(bytes).map_err(|e| e.to_string())?;
+        committer.check_binding()?;
+        Ok(committer)
+    }
+
+    // load_from_path reads a committer serialized with bincode from a file.
+    pub fn load_from_path<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        Committer::from_bytes(&bytes)
+    }
+
+    // from_generators_file reads a generator set written with
+    // write_generators_file, so that nodes share the same generators without
+    // exchanging a committer.
+    pub fn from_generators_file(path: &Path) -> io::Result<Committer> {
+        let bytes = fs::read(path)?;
+        let generators: Vec<RistrettoPoint> = bincode::deserialize(&bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Committer::from_generators(generators)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // write_generators_file writes the generators serialized with bincode.
+    pub fn write_generators_file(&self, path: &Path) -> io::Result<()> {
+        let bytes = bincode::serialize(&self.generators)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, bytes)
+    }
+
+    // check_binding rejects generator sets that lose the binding property:
+    // an identity generator, or a generator repeated, lets the committed
+    // values in those columns be changed without changing the commitment.
+    pub fn check_binding(&self) -> Result<(), String> {
+        let mut seen = HashSet::with_capacity(self.generators.len() + 1);
+        for (i, generator) in self
+            .generators
+            .iter()
+            .chain(self.blinding_generator.iter())
+            .enumerate()
+        {
+            if *generator == RistrettoPoint::identity() {
+                return Err(format!("Generator {} is the identity", i));
+            }
+            if !seen.insert(generator.compress().to_bytes()) {
+                return Err(format!("Generator {} is repeated", i));
+            }
+        }
+        Ok(())
+    }
+
     pub fn len(&self) -> usize {
         self.generators.len()
     }
 
-    pub fn commit(&self, scalars: &[Scalar]) -> Result<RistrettoPoint, String> {
-        if scalars.len() > self.generators.len() {
-            println!(
-                "Chunk size is too large. Expected {}, got {}",
-                self.generators.len(),
-                scalars.len()
-            );
-            return Err("Chunk size is too large".to_string());
+    pub fn generators(&self) -> &[RistrettoPoint] {
+        &self.generators
+    }
+
+    // generators_hash identifies the generators, so two parties can check
+    // they commit with the same ones. It uses the hash function selected by
+    // the crate features, like Message::commitments_hash.
+    pub fn generators_hash(&self) -> [u8; 32] {
+        hash::digest(&bincode::serialize(&self.generators).unwrap())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.generators.is_empty()
+    }
+
+    // concat returns a committer with the generators of self followed by those
+    // of other. It commits to chunks of two generations concatenated with
+    // concat_chunk, see concat_commitments.
+    pub fn concat(&self, other: &Committer) -> Committer {
+        Committer::with_generators(
+            [&self.generators[..], &other.generators[..]].concat(),
+        )
+    }
+
+    // concat_chunk concatenates a chunk committed with self with a chunk
+    // committed with another committer. The first chunk has to span all the
+    // generators of self so the second one lines up with the appended
+    // generators.
+    pub fn concat_chunk(
+        &self,
+        first: &[Scalar],
+        second: &[Scalar],
+    ) -> Result<Vec<Scalar>, BlockError> {
+        if first.len() != self.generators.len() {
+            return Err(BlockError::ConcatChunkLen {
+                expected: self.generators.len(),
+                got: first.len(),
+            });
         }
+        Ok([first, second].concat())
+    }
+
+    // audit_message checks that the message is consistent with its
+    // commitments without keeping any state, for nodes that only monitor the
+    // network.
+    pub fn audit_message(&self, msg: &Message) -> Result<(), String> {
+        msg.verify(self)
+    }
+
+    // commit runs in constant time, so it does not use the precomputed
+    // tables: curve25519-dalek only precomputes variable time multiscalar
+    // multiplications, see commit_vartime.
+    pub fn commit(
+        &self,
+        scalars: &[Scalar],
+    ) -> Result<RistrettoPoint, BlockError> {
+        self.check_commit_len(scalars)?;
         Ok(RistrettoPoint::multiscalar_mul(
             scalars,
             &self.generators[..scalars.len()],
         ))
     }
+
+    fn check_commit_len(&self, scalars: &[Scalar]) -> Result<(), BlockError> {
+        if scalars.len() > self.generators.len() {
+            return Err(BlockError::ChunkTooLarge {
+                expected: self.generators.len(),
+                got: scalars.len(),
+            });
+        }
+        Ok(())
+    }
+
+    // commit_vartime is commit with a variable time multiscalar
+    // multiplication, only for public data since its running time depends on
+    // the scalars. Full chunks use the precomputed tables, which need a scalar
+    // for every generator.
+    pub fn commit_vartime(
+        &self,
+        scalars: &[Scalar],
+    ) -> Result<RistrettoPoint, BlockError> {
+        self.check_commit_len(scalars)?;
+        if !scalars.is_empty() && scalars.len() == self.generators.len() {
+            return Ok(self.precomputation().vartime_multiscalar_mul(scalars));
+        }
+        Ok(RistrettoPoint::vartime_multiscalar_mul(
+            scalars,
+            &self.generators[..scalars.len()],
+        ))
+    }
+
+    // commit_hiding returns commit(scalars) + blinding * H, which reveals
+    // nothing about the scalars as long as the blinding factor is random and
+    // secret. The committer must have been created with new_hiding. The
+    // scalars are secret here, so it is computed in constant time.
+    pub fn commit_hiding(
+        &self,
+        scalars: &[Scalar],
+        blinding: Scalar,
+    ) -> Result<RistrettoPoint, BlockError> {
+        let h = self
+            .blinding_generator
+            .ok_or(BlockError::NoBlindingGenerator)?;
+        self.check_commit_len(scalars)?;
+        Ok(RistrettoPoint::multiscalar_mul(
+            scalars.iter().chain([&blinding]),
+            self.generators[..scalars.len()].iter().chain([&h]),
+        ))
+    }
+
+    // batch_commit commits to every chunk, across threads with the parallel
+    // feature. It fails if any chunk is longer than the committer.
+    pub fn batch_commit(
+        &self,
+        chunks: &[Vec<Scalar>],
+    ) -> Result<Vec<RistrettoPoint>, BlockError> {
+        if let Some((i, chunk)) = chunks
+            .iter()
+            .enumerate()
+            .find(|(_, chunk)| chunk.len() > self.generators.len())
+        {
+            return Err(BlockError::BatchChunkTooLarge {
+                chunk: i,
+                expected: self.generators.len(),
+                got: chunk.len(),
+            });
+        }
+        #[cfg(feature = "parallel")]
+        return chunks
+            .par_iter()
+            .map(|chunk| self.commit_vartime(chunk))
+            .collect();
+        #[cfg(not(feature = "parallel"))]
+        return chunks
+            .iter()
+            .map(|chunk| self.commit_vartime(chunk))
+            .collect();
+    }
+
+    fn precomputation(&self) -> Arc<VartimeRistrettoPrecomputation> {
+        let mut cache = self.precomputation.lock().unwrap();
+        cache
+            .get_or_insert_with(|| {
+                self.precomputation_builds.fetch_add(1, Ordering::Relaxed);
+                Arc::new(VartimeRistrettoPrecomputation::new(&self.generators))
+            })
+            .clone()
+    }
+
+    // clear_caches drops the precomputed tables to release their memory, they
+    // are built again on the next commit_vartime that needs them.
+    pub fn clear_caches(&self) {
+        self.precomputation.lock().unwrap().take();
+    }
 }
 
-// TODO: read the points from file instead of computing them at runtime
-fn generators(n: usize) -> Vec<RistrettoPoint> {
-    let mut rng = rand::thread_rng();
-    (0..n)
-        .map(|_| RISTRETTO_BASEPOINT_POINT * Scalar::from(rng.gen::<u128>()))
+// Domain separation tag for the generator derivation.
+const GENERATORS_DOMAIN: &[u8] = b"rlnc_poc generators";
+
+// generators_from_seed maps SHA-512(domain || seed || index) to the curve for
+// each index. The hash is fixed regardless of the hash features so that the
+// generators don't depend on how the crate was built.
+fn generators_from_seed(seed: &[u8; 32], n: usize) -> Vec<RistrettoPoint> {
+    (0..n as u64)
+        .map(|i| {
+            let hash = Sha512::new()
+                .chain_update(GENERATORS_DOMAIN)
+                .chain_update(seed)
+                .chain_update(i.to_le_bytes())
+                .finalize();
+            RistrettoPoint::from_uniform_bytes(&hash.into())
+        })
         .collect()
 }
 
+// concat_commitments returns the commitments of the generation obtained by
+// concatenating the chunks of two generations with Committer::concat_chunk,
+// each one is the sum of the commitments of its parts.
+pub fn concat_commitments(
+    first: &[RistrettoPoint],
+    second: &[RistrettoPoint],
+) -> Result<Vec<RistrettoPoint>, String> {
+    if first.len() != second.len() {
+        return Err(
+            "The generations have a different number of chunks".to_string()
+        );
+    }
+    Ok(first.iter().zip(second).map(|(a, b)| a + b).collect())
+}
+
 // chunk_to_scalars returns a vector of scalars in the Ristretto curve from the
 // given array, it works modulo the characteristic of the Ristretto Scalar field.
 // In real life blocks need to be encoded by bitpacking so that each 256 bits have
 // the last couple of them zeroed.
-pub fn chunk_to_scalars(chunk: &[u8]) -> Result<Vec<Scalar>, String> {
-    if chunk.len() % 32 != 0 {
-        return Err("Chunk size is not divisible by 32".to_string());
+pub fn chunk_to_scalars(chunk: &[u8]) -> Result<Vec<Scalar>, BlockError> {
+    if !chunk.len().is_multiple_of(32) {
+        return Err(BlockError::NotDivisibleBy32 {
+            chunk_len: chunk.len(),
+        });
     }
     Ok(chunk
         .chunks(63 * 32)
-        .map(|x| {
+        .flat_map(|x| {
             let mut tail_bits = [0u8; 32];
             let mut scalars: Vec<Scalar> = x
                 .chunks_exact(32)
@@ -75,12 +496,11 @@ pub fn chunk_to_scalars(chunk: &[u8]) -> Result<Vec<Scalar>, String> {
             scalars.push(Scalar::from_bytes_mod_order(tail_bits));
             scalars
         })
-        .flatten()
         .collect())
 }
 
 pub fn chunk_to_scalars_31(chunk: &[u8]) -> Result<Vec<Scalar>, String> {
-    if chunk.len() % 31 != 0 {
+    if !chunk.len().is_multiple_of(31) {
         return Err(format!(
             "Chunk size is not divisible by 31. It is {}",
             chunk.len()
@@ -96,9 +516,35 @@ pub fn chunk_to_scalars_31(chunk: &[u8]) -> Result<Vec<Scalar>, String> {
         .collect())
 }
 
-// random_u8_slice returns a vector of random u32 numbers of the given length.
+// max_chunk_len returns the size in bytes of the largest chunk that
+// chunk_to_scalars maps to at most num_scalars scalars. Every group of 63 words
+// of 32 bytes takes an extra scalar for the high bits.
+pub fn max_chunk_len(num_scalars: usize) -> usize {
+    let words = num_scalars / 64 * 63 + (num_scalars % 64).saturating_sub(1);
+    words * 32
+}
+
+// scalar_to_u64 returns the scalar as an integer when it is smaller than
+// 2^64, as the coefficients of fresh messages are.
+pub fn scalar_to_u64(s: &Scalar) -> Option<u64> {
+    let bytes = s.as_bytes();
+    if bytes[8..].iter().any(|&b| b != 0) {
+        return None;
+    }
+    let mut low = [0u8; 8];
+    low.copy_from_slice(&bytes[..8]);
+    Some(u64::from_le_bytes(low))
+}
+
+// random_u8_slice returns a vector of random bytes of the given length, with
+// the last byte of every 32-byte word zeroed.
 pub fn random_u8_slice(length: usize) -> Vec<u8> {
-    let mut rng = rand::thread_rng();
+    random_u8_slice_from(length, &mut rand::thread_rng())
+}
+
+// random_u8_slice_from is random_u8_slice drawing the bytes from the given rng,
+// a seeded one gives reproducible blocks.
+pub fn random_u8_slice_from<R: Rng>(length: usize, rng: &mut R) -> Vec<u8> {
     let mut ret: Vec<u8> = (0..length).map(|_| rng.gen()).collect();
     for i in (31..length).step_by(32) {
         ret[i] = 0;
@@ -106,21 +552,123 @@ pub fn random_u8_slice(length: usize) -> Vec<u8> {
     ret
 }
 
+// random_u8_slice_seeded is random_u8_slice drawn from a StdRng seeded with
+// seed, the same seed always gives the same block.
+pub fn random_u8_slice_seeded(length: usize, seed: u64) -> Vec<u8> {
+    random_u8_slice_from(length, &mut StdRng::seed_from_u64(seed))
+}
+
+// validate_block checks upfront that the block can be encoded by new_source in
+// num_chunks chunks with the given committer. It also requires every 32-byte
+// word to be a canonical scalar, as produced by random_u8_slice, so the block
+// stays valid for framings that map words to scalars directly.
+pub fn validate_block(
+    block: &[u8],
+    num_chunks: usize,
+    committer: &Committer,
+) -> Result<(), BlockError> {
+    if num_chunks == 0 {
+        return Err(BlockError::ZeroChunks);
+    }
+    if !block.len().is_multiple_of(num_chunks) {
+        return Err(BlockError::NotDivisibleByNumChunks {
+            block_len: block.len(),
+            num_chunks,
+        });
+    }
+    let chunk_len = block.len() / num_chunks;
+    if !chunk_len.is_multiple_of(32) {
+        return Err(BlockError::NotDivisibleBy32 { chunk_len });
+    }
+    let words = chunk_len / 32;
+    let needed = words + words.div_ceil(63);
+    if needed > committer.len() {
+        return Err(BlockError::ChunkTooLarge {
+            expected: committer.len(),
+            got: needed,
+        });
+    }
+    for (i, word) in block.chunks_exact(32).enumerate() {
+        let mut array = [0u8; 32];
+        array.copy_from_slice(word);
+        if Scalar::from_canonical_bytes(array).is_none().into() {
+            return Err(BlockError::NonCanonicalScalar {
+                chunk: i / words,
+                column: i % words,
+            });
+        }
+    }
+    Ok(())
+}
+
+// block_to_chunks splits the block in num_chunks chunks of equal size. An empty
+// block gives num_chunks empty chunks.
 pub fn block_to_chunks(
     block: &[u8],
     num_chunks: usize,
-) -> Result<Vec<&[u8]>, String> {
-    if block.len() % num_chunks != 0 {
-        return Err("Block size is not divisible by num_chunks".to_string());
+) -> Result<Vec<&[u8]>, BlockError> {
+    if num_chunks == 0 {
+        return Err(BlockError::ZeroChunks);
+    }
+    if !block.len().is_multiple_of(num_chunks) {
+        return Err(BlockError::NotDivisibleByNumChunks {
+            block_len: block.len(),
+            num_chunks,
+        });
+    }
+    if block.is_empty() {
+        return Ok(vec![block; num_chunks]);
     }
     let chunk_size = block.len() / num_chunks;
     Ok(block.chunks(chunk_size).collect())
 }
 
+// Size of the length header prepended to padded blocks.
+pub const PADDING_HEADER_LEN: usize = 8;
+
+// pad_block prepends the length of the block as a little endian u64 and pads
+// it with zeros so that it splits in num_chunks chunks whose size is a
+// multiple of 32 bytes. Blocks of any size, including empty ones, can then be
+// encoded, and unpad_block recovers the original bytes after decoding.
+pub fn pad_block(
+    block: &[u8],
+    num_chunks: usize,
+) -> Result<Vec<u8>, BlockError> {
+    if num_chunks == 0 {
+        return Err(BlockError::ZeroChunks);
+    }
+    let padded_len =
+        (PADDING_HEADER_LEN + block.len()).next_multiple_of(num_chunks * 32);
+    let mut padded = Vec::with_capacity(padded_len);
+    padded.extend_from_slice(&(block.len() as u64).to_le_bytes());
+    padded.extend_from_slice(block);
+    padded.resize(padded_len, 0);
+    Ok(padded)
+}
+
+// unpad_block returns the original block from a block padded by pad_block.
+pub fn unpad_block(padded: &[u8]) -> Result<Vec<u8>, BlockError> {
+    if padded.len() < PADDING_HEADER_LEN {
+        return Err(BlockError::PaddedBlockTooShort { len: padded.len() });
+    }
+    let mut header = [0u8; PADDING_HEADER_LEN];
+    header.copy_from_slice(&padded[..PADDING_HEADER_LEN]);
+    let len = u64::from_le_bytes(header) as usize;
+    if len > padded.len() - PADDING_HEADER_LEN {
+        return Err(BlockError::PaddedLenTooLarge {
+            len,
+            available: padded.len() - PADDING_HEADER_LEN,
+        });
+    }
+    Ok(padded[PADDING_HEADER_LEN..PADDING_HEADER_LEN + len].to_vec())
+}
+
 // scalars_to_chunk converts a vector of Scalars back into bytes, reversing the
 // transformation done by chunk_to_scalars. It extracts the tail bits from the last
-// scalar of each 255-scalar chunk and combines them with the main bytes.
-pub fn scalars_to_chunk(scalars: &[Scalar]) -> Result<Vec<u8>, String> {
+// scalar of each group of 64 scalars and combines them with the main bytes. A
+// trailing group of k + 1 scalars holds the last k words, so a trailing group
+// of a single scalar can't come from chunk_to_scalars.
+pub fn scalars_to_chunk(scalars: &[Scalar]) -> Result<Vec<u8>, BlockError> {
     if scalars.is_empty() {
         return Ok(Vec::new());
     }
@@ -142,7 +690,7 @@ pub fn scalars_to_chunk(scalars: &[Scalar]) -> Result<Vec<u8>, String> {
 
     for chunk in scalars.chunks(chunk_size) {
         if chunk.len() <= 1 {
-            return Err("Invalid scalar chunk size: each chunk must have enough scalars to contain data and tail bits".to_string());
+            return Err(BlockError::InvalidScalarChunk { len: chunk.len() });
         }
 
         // The last scalar in the chunk contains the tail bits
@@ -173,12 +721,86 @@ pub fn scalars_to_chunk_31(scalars: &[Scalar]) -> Vec<u8> {
         .collect()
 }
 
+// Number of 4-bit nibbles packed in each scalar by pack_bytes_to_scalars.
+const NIBBLES_PER_SCALAR: usize = 63;
+
+// pack_bytes_to_scalars packs the length of the bytes as a little endian u64
+// followed by the bytes into scalars, 252 bits per scalar. The scalar field
+// order is just above 2^252, so that is the most that always fits without
+// reduction. unpack_scalars_to_bytes recovers exactly the original bytes.
+pub fn pack_bytes_to_scalars(bytes: &[u8]) -> Vec<Scalar> {
+    let nibbles: Vec<u8> = (bytes.len() as u64)
+        .to_le_bytes()
+        .iter()
+        .chain(bytes)
+        .flat_map(|b| [b & 0x0F, b >> 4])
+        .collect();
+    nibbles
+        .chunks(NIBBLES_PER_SCALAR)
+        .map(|x| {
+            let mut array = [0u8; 32];
+            x.iter()
+                .enumerate()
+                .for_each(|(i, n)| array[i / 2] |= n << (4 * (i % 2)));
+            Scalar::from_bytes_mod_order(array)
+        })
+        .collect()
+}
+
+// unpack_scalars_to_bytes reverses pack_bytes_to_scalars.
+pub fn unpack_scalars_to_bytes(
+    scalars: &[Scalar],
+) -> Result<Vec<u8>, BlockError> {
+    let mut nibbles = Vec::with_capacity(scalars.len() * NIBBLES_PER_SCALAR);
+    for (i, scalar) in scalars.iter().enumerate() {
+        let array = scalar.to_bytes();
+        if array[31] >> 4 != 0 {
+            return Err(BlockError::ScalarTooWide { index: i });
+        }
+        nibbles.extend(
+            (0..NIBBLES_PER_SCALAR)
+                .map(|j| (array[j / 2] >> (4 * (j % 2))) & 0x0F),
+        );
+    }
+    let bytes: Vec<u8> = nibbles
+        .chunks_exact(2)
+        .map(|x| x[0] | (x[1] << 4))
+        .collect();
+    unpad_block(&bytes)
+}
+
 #[cfg(test)]
 mod tests {
     use rand::thread_rng;
 
     use super::*;
 
+    #[test]
+    fn test_pack_bytes_to_scalars() {
+        let mut rng = thread_rng();
+        assert_eq!(pack_bytes_to_scalars(&[]).len(), 1);
+        assert_eq!(
+            unpack_scalars_to_bytes(&pack_bytes_to_scalars(&[])),
+            Ok(vec![])
+        );
+        for _ in 0..200 {
+            let len: usize = rng.gen_range(0..2000);
+            let bytes: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let scalars = pack_bytes_to_scalars(&bytes);
+            assert_eq!(scalars.len(), (16 + 2 * len).div_ceil(63));
+            assert_eq!(unpack_scalars_to_bytes(&scalars).unwrap(), bytes);
+        }
+
+        let mut scalars = pack_bytes_to_scalars(&[0xff; 100]);
+        scalars[1] = -Scalar::ONE;
+        assert!(unpack_scalars_to_bytes(&scalars).is_err());
+        assert!(unpack_scalars_to_bytes(
+            &pack_bytes_to_scalars(&[1; 100])[..2]
+        )
+        .is_err());
+        assert!(unpack_scalars_to_bytes(&[]).is_err());
+    }
+
     #[test]
     fn test_roundtrip_chunk_conversion() {
         // Test with one chunk (63*32 bytes) and multiple chunks
@@ -202,6 +824,473 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_roundtrip_partial_groups() {
+        // Every number of words up to three groups, including 65 and 127
+        // words whose last group is partial.
+        for words in (1..=3 * 63 + 1).chain([65, 127]) {
+            let mut original = vec![0u8; words * 32];
+            thread_rng().fill(&mut original[..]);
+            let scalars = chunk_to_scalars(&original).unwrap();
+            assert_eq!(scalars.len(), words + words.div_ceil(63));
+            assert_eq!(
+                scalars_to_chunk(&scalars).unwrap(),
+                original,
+                "Failed roundtrip for {} words",
+                words
+            );
+        }
+        for len in [1, 65, 129] {
+            assert!(scalars_to_chunk(&vec![Scalar::ONE; len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_block_to_chunks_edge_cases() {
+        assert!(block_to_chunks(&[0u8; 64], 0).is_err());
+        let chunks = block_to_chunks(&[], 3).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|c| c.is_empty()));
+        assert!(chunk_to_scalars(&[]).unwrap().is_empty());
+        assert!(scalars_to_chunk(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_pad_block() {
+        for len in [0, 1, 23, 24, 25, 1000] {
+            let mut block = vec![0u8; len];
+            thread_rng().fill(&mut block[..]);
+            let padded = pad_block(&block, 3).unwrap();
+            assert!(padded.len().is_multiple_of(3 * 32));
+            assert!(padded.len() >= len + 8);
+            assert_eq!(unpad_block(&padded).unwrap(), block);
+        }
+        assert!(pad_block(&[1], 0).is_err());
+        assert!(unpad_block(&[1, 0]).is_err());
+        assert!(unpad_block(&[9, 0, 0, 0, 0, 0, 0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_clear_caches() {
+        let committer = Committer::new(4);
+        let scalars: Vec<Scalar> = (1..=4u64).map(Scalar::from).collect();
+        let expected =
+            RistrettoPoint::multiscalar_mul(&scalars, &committer.generators);
+        assert_eq!(committer.commit_vartime(&scalars).unwrap(), expected);
+        assert_eq!(committer.commit_vartime(&scalars).unwrap(), expected);
+        assert_eq!(committer.precomputation_builds.load(Ordering::Relaxed), 1);
+
+        committer.clear_caches();
+        assert!(committer.precomputation.lock().unwrap().is_none());
+        assert_eq!(committer.commit_vartime(&scalars[..2]).unwrap(), {
+            RistrettoPoint::multiscalar_mul(
+                &scalars[..2],
+                &committer.generators[..2],
+            )
+        });
+        assert!(committer.precomputation.lock().unwrap().is_none());
+        // The constant time commit never uses the tables.
+        assert_eq!(committer.commit(&scalars).unwrap(), expected);
+        assert!(committer.precomputation.lock().unwrap().is_none());
+        assert_eq!(committer.commit_vartime(&scalars).unwrap(), expected);
+        assert_eq!(committer.precomputation_builds.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_precomputed_commit() {
+        let mut rng = rand::thread_rng();
+        let committer = Committer::new(16);
+        assert!(committer.precomputation.lock().unwrap().is_none());
+        for len in [1, 8, 15, 16] {
+            let scalars: Vec<Scalar> =
+                (0..len).map(|_| Scalar::from(rng.gen::<u64>())).collect();
+            assert_eq!(
+                committer.commit_vartime(&scalars).unwrap(),
+                RistrettoPoint::multiscalar_mul(
+                    &scalars,
+                    &committer.generators[..len]
+                )
+            );
+        }
+        assert_eq!(committer.precomputation_builds.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_commit_too_large() {
+        let committer = Committer::new(4);
+        let scalars = vec![Scalar::ONE; 6];
+        let expected = BlockError::ChunkTooLarge {
+            expected: 4,
+            got: 6,
+        };
+        assert_eq!(committer.commit(&scalars), Err(expected.clone()));
+        assert_eq!(committer.commit_vartime(&scalars), Err(expected.clone()));
+        assert_eq!(
+            expected.to_string(),
+            "Chunk size is too large. Expected 4, got 6"
+        );
+    }
+
+    #[test]
+    fn test_block_errors() {
+        assert_eq!(
+            chunk_to_scalars(&[0u8; 33]),
+            Err(BlockError::NotDivisibleBy32 { chunk_len: 33 })
+        );
+        assert_eq!(block_to_chunks(&[0u8; 64], 0), Err(BlockError::ZeroChunks));
+        assert_eq!(
+            block_to_chunks(&[0u8; 64], 3),
+            Err(BlockError::NotDivisibleByNumChunks {
+                block_len: 64,
+                num_chunks: 3
+            })
+        );
+        // 65 scalars leave a trailing group of one, without data.
+        assert_eq!(
+            scalars_to_chunk(&[Scalar::ONE; 65]),
+            Err(BlockError::InvalidScalarChunk { len: 1 })
+        );
+        let e: String = BlockError::ZeroChunks.into();
+        assert_eq!(e, "The number of chunks must be positive");
+        assert_eq!(
+            block_to_chunks(&[0u8; 64], 3).unwrap_err().to_string(),
+            "Block size 64 is not divisible by num_chunks 3"
+        );
+        assert_eq!(
+            unpad_block(&[9, 0, 0, 0, 0, 0, 0, 0, 1]),
+            Err(BlockError::PaddedLenTooLarge {
+                len: 9,
+                available: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_commit_vartime() {
+        let mut rng = rand::thread_rng();
+        let committer = Committer::new(16);
+        for len in [0, 1, 8, 15, 16] {
+            let scalars: Vec<Scalar> =
+                (0..len).map(|_| Scalar::from(rng.gen::<u64>())).collect();
+            assert_eq!(
+                committer.commit_vartime(&scalars).unwrap(),
+                committer.commit(&scalars).unwrap()
+            );
+        }
+        let scalars = vec![Scalar::ONE; 17];
+        assert_eq!(
+            committer.commit_vartime(&scalars),
+            committer.commit(&scalars)
+        );
+    }
+
+    #[test]
+    fn test_batch_commit() {
+        let mut rng = rand::thread_rng();
+        let committer = Committer::new(8);
+        let chunks: Vec<Vec<Scalar>> = (0..20)
+            .map(|i| {
+                (0..1 + i % 8)
+                    .map(|_| Scalar::from(rng.gen::<u64>()))
+                    .collect()
+            })
+            .collect();
+        let expected: Vec<RistrettoPoint> = chunks
+            .iter()
+            .map(|chunk| committer.commit(chunk).unwrap())
+            .collect();
+        assert_eq!(committer.batch_commit(&chunks).unwrap(), expected);
+        assert!(committer.batch_commit(&[]).unwrap().is_empty());
+
+        let mut chunks = chunks;
+        chunks[5].resize(9, Scalar::ONE);
+        chunks[7].resize(10, Scalar::ONE);
+        assert_eq!(
+            committer.batch_commit(&chunks),
+            Err(BlockError::BatchChunkTooLarge {
+                chunk: 5,
+                expected: 8,
+                got: 9
+            })
+        );
+    }
+
+    #[test]
+    fn test_commit_hiding() {
+        let mut rng = rand::thread_rng();
+        let committer = Committer::new_hiding(4);
+        assert_eq!(committer.len(), 4);
+        assert!(committer.check_binding().is_ok());
+        let h = committer.blinding_generator.unwrap();
+        assert!(!committer.generators.contains(&h));
+
+        let scalars = vec![Scalar::ZERO; 4];
+        let (r, s) = (Scalar::from(rng.gen::<u64>()), Scalar::from(3u8));
+        let first = committer.commit_hiding(&scalars, r).unwrap();
+        let second = committer.commit_hiding(&scalars, s).unwrap();
+        assert_ne!(first, second);
+        assert_ne!(first, committer.commit(&scalars).unwrap());
+        // Both open with the matching blinding factor only.
+        assert_eq!(first - r * h, committer.commit(&scalars).unwrap());
+        assert_eq!(second - s * h, committer.commit(&scalars).unwrap());
+        assert_ne!(first - s * h, committer.commit(&scalars).unwrap());
+
+        // A full-length chunk matches the constant time reference.
+        let scalars: Vec<Scalar> =
+            (0..4).map(|_| Scalar::from(rng.gen::<u64>())).collect();
+        assert_eq!(
+            committer.commit_hiding(&scalars, r).unwrap(),
+            RistrettoPoint::multiscalar_mul(
+                scalars.iter().chain([&r]),
+                committer.generators.iter().chain([&h]),
+            )
+        );
+
+        assert_eq!(
+            committer.commit_hiding(&[Scalar::ONE; 5], r),
+            Err(BlockError::ChunkTooLarge {
+                expected: 4,
+                got: 5
+            })
+        );
+        assert_eq!(
+            Committer::new(4).commit_hiding(&scalars, r),
+            Err(BlockError::NoBlindingGenerator)
+        );
+    }
+
+    #[test]
+    fn test_check_binding() {
+        let committer = Committer::new(4);
+        assert!(committer.check_binding().is_ok());
+        let bytes = bincode::serialize(&committer).unwrap();
+        assert_eq!(
+            Committer::from_bytes(&bytes).unwrap().generators,
+            committer.generators
+        );
+
+        let mut generators = committer.generators.clone();
+        generators[2] = RistrettoPoint::identity();
+        let bad = Committer::with_generators(generators);
+        assert_eq!(
+            bad.check_binding(),
+            Err("Generator 2 is the identity".to_string())
+        );
+        let bytes = bincode::serialize(&bad).unwrap();
+        assert!(Committer::from_bytes(&bytes).is_err());
+
+        let mut generators = committer.generators.clone();
+        generators[3] = generators[1];
+        let bad = Committer::with_generators(generators);
+        assert_eq!(
+            bad.check_binding(),
+            Err("Generator 3 is repeated".to_string())
+        );
+
+        let path = std::env::temp_dir()
+            .join(format!("rlnc_committer_{}", std::process::id()));
+        fs::write(&path, bincode::serialize(&committer).unwrap()).unwrap();
+        assert_eq!(Committer::load_from_path(&path).unwrap().len(), 4);
+        fs::remove_file(&path).unwrap();
+        assert!(Committer::load_from_path(&path).is_err());
+    }
+
+    #[test]
+    fn test_generators_file() {
+        let committer = Committer::new(380);
+        let path = std::env::temp_dir()
+            .join(format!("rlnc_generators_{}", std::process::id()));
+        committer.write_generators_file(&path).unwrap();
+        let loaded = Committer::from_generators_file(&path).unwrap();
+        assert_eq!(loaded.generators, committer.generators);
+
+        fs::write(
+            &path,
+            bincode::serialize(&Vec::<RistrettoPoint>::new()).unwrap(),
+        )
+        .unwrap();
+        assert!(Committer::from_generators_file(&path).is_err());
+        fs::write(&path, [1, 2, 3]).unwrap();
+        assert!(Committer::from_generators_file(&path).is_err());
+        fs::remove_file(&path).unwrap();
+        assert!(Committer::from_generators_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_from_generators() {
+        let committer = Committer::new(8);
+        let copy = Committer::from_generators(committer.generators().to_vec())
+            .unwrap();
+        assert_eq!(copy.generators(), committer.generators());
+        for len in [1, 5, 8] {
+            let scalars: Vec<Scalar> =
+                (0..len).map(|i| Scalar::from(i as u64 + 3)).collect();
+            assert_eq!(
+                copy.commit(&scalars).unwrap(),
+                committer.commit(&scalars).unwrap()
+            );
+        }
+
+        assert!(Committer::from_generators(Vec::new()).is_err());
+        let mut generators = committer.generators().to_vec();
+        generators[1] = generators[0];
+        assert_eq!(
+            Committer::from_generators(generators).err(),
+            Some("Generator 1 is repeated".to_string())
+        );
+    }
+
+    #[test]
+    fn test_generators_hash() {
+        let committer = Committer::from_seed([3; 32], 8);
+        assert_eq!(
+            committer.generators_hash(),
+            Committer::from_seed([3; 32], 8).generators_hash()
+        );
+        assert_ne!(
+            committer.generators_hash(),
+            Committer::from_seed([4; 32], 8).generators_hash()
+        );
+        assert_ne!(
+            committer.generators_hash(),
+            Committer::from_seed([3; 32], 7).generators_hash()
+        );
+    }
+
+    #[test]
+    fn test_from_seed() {
+        let committer = Committer::from_seed([7; 32], 16);
+        assert_eq!(committer.len(), 16);
+        assert!(committer.check_binding().is_ok());
+        assert_eq!(
+            bincode::serialize(&committer).unwrap(),
+            bincode::serialize(&Committer::from_seed([7; 32], 16)).unwrap()
+        );
+        // A shorter committer is a prefix of a longer one.
+        assert_eq!(
+            Committer::from_seed([7; 32], 4).generators,
+            committer.generators[..4]
+        );
+        let other = Committer::from_seed([8; 32], 16);
+        assert!(other
+            .generators
+            .iter()
+            .all(|g| !committer.generators.contains(g)));
+        assert!(
+            std::panic::catch_unwind(|| Committer::from_seed([7; 32], 0))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_generators_unrelated_to_basepoint() {
+        use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+
+        let committer = Committer::new(64);
+        let multiples: HashSet<[u8; 32]> = (0..1000u32)
+            .map(|k| {
+                (RISTRETTO_BASEPOINT_POINT * Scalar::from(k))
+                    .compress()
+                    .to_bytes()
+            })
+            .collect();
+        for generator in committer.generators.iter().step_by(4) {
+            assert!(!multiples.contains(&generator.compress().to_bytes()));
+            assert!(!multiples.contains(&(-generator).compress().to_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_validate_block() {
+        let committer = Committer::new(3);
+        let mut block = random_u8_slice(3 * 64);
+        assert_eq!(validate_block(&block, 3, &committer), Ok(()));
+        assert_eq!(
+            validate_block(&block, 0, &committer),
+            Err(BlockError::ZeroChunks)
+        );
+        assert_eq!(
+            validate_block(&block, 5, &committer),
+            Err(BlockError::NotDivisibleByNumChunks {
+                block_len: 192,
+                num_chunks: 5
+            })
+        );
+        assert_eq!(
+            validate_block(&block, 4, &committer),
+            Err(BlockError::NotDivisibleBy32 { chunk_len: 48 })
+        );
+        assert_eq!(
+            validate_block(&block, 1, &committer),
+            Err(BlockError::ChunkTooLarge {
+                expected: 3,
+                got: 7
+            })
+        );
+
+        // Second word of the third chunk.
+        block[2 * 64 + 63] = 0xff;
+        let e = validate_block(&block, 3, &committer).unwrap_err();
+        assert_eq!(
+            e,
+            BlockError::NonCanonicalScalar {
+                chunk: 2,
+                column: 1
+            }
+        );
+        assert_eq!(
+            e.to_string(),
+            "Word 1 of chunk 2 is not a canonical scalar"
+        );
+    }
+
+    #[test]
+    fn test_scalar_to_u64() {
+        for x in [0, 1, 255, 1 << 40, u64::MAX] {
+            assert_eq!(scalar_to_u64(&Scalar::from(x)), Some(x));
+        }
+        assert_eq!(scalar_to_u64(&Scalar::from(1u128 << 64)), None);
+        assert_eq!(scalar_to_u64(&-Scalar::ONE), None);
+        let product = Scalar::from(u64::MAX) * Scalar::from(u64::MAX);
+        assert_eq!(scalar_to_u64(&product), None);
+    }
+
+    #[test]
+    fn test_random_u8_slice_from() {
+        let block = random_u8_slice_from(100, &mut StdRng::seed_from_u64(1));
+        assert_eq!(
+            block,
+            random_u8_slice_from(100, &mut StdRng::seed_from_u64(1))
+        );
+        assert_ne!(
+            block,
+            random_u8_slice_from(100, &mut StdRng::seed_from_u64(2))
+        );
+        assert_eq!(block.len(), 100);
+        assert!(block[31] == 0 && block[63] == 0 && block[95] == 0);
+    }
+
+    #[test]
+    fn test_random_u8_slice_seeded() {
+        let block = random_u8_slice_seeded(200, 7);
+        assert_eq!(block, random_u8_slice_seeded(200, 7));
+        assert_ne!(block, random_u8_slice_seeded(200, 8));
+        assert!((31..200).step_by(32).all(|i| block[i] == 0));
+        assert!(block.iter().any(|&x| x != 0));
+    }
+
+    #[test]
+    fn test_max_chunk_len() {
+        for num_scalars in [0, 1, 2, 5, 63, 64, 65, 66, 128, 200] {
+            let len = max_chunk_len(num_scalars);
+            assert!(
+                chunk_to_scalars(&vec![0u8; len]).unwrap().len() <= num_scalars
+            );
+            let next = chunk_to_scalars(&vec![0u8; len + 32]).unwrap();
+            assert!(next.len() > num_scalars);
+        }
+    }
+
     #[test]
     fn test_roundtrip_chunk_31_conversion() {
         let test_sizes = vec![31, 31 * 2, 31 * 8];
@@ -1,14 +1,77 @@
+// The exported functions take raw handles from C callers, who are responsible
+// for passing pointers obtained from this library.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
 use std::ptr;
 
-use crate::blocks::Committer;
+use crate::blocks::{max_chunk_len, Committer};
 use crate::node::{Message, Node, ReceiveError};
 
+// export_bytes hands the buffer over to the C caller, who must release it with
+// free_buffer passing back the same length.
+fn export_bytes(data: Vec<u8>, out_ptr: *mut *mut u8, out_len: *mut usize) {
+    let boxed = data.into_boxed_slice();
+    unsafe {
+        *out_len = boxed.len();
+        *out_ptr = Box::into_raw(boxed) as *mut u8;
+    }
+}
+
+thread_local! {
+    // Message of the last failing call on this thread, see get_last_error.
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// set_last_error records the error of a failing call and passes the code
+// through.
+fn set_last_error(error: impl ToString, code: i32) -> i32 {
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(error.to_string()));
+    code
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|e| e.borrow_mut().take());
+}
+
+// Code returned by the functions returning an integer when they panic.
+pub const PANIC_CODE: i32 = -100;
+
+// Code returned by the functions returning an integer when passed a null
+// pointer, the functions returning a pointer return null instead.
+pub const NULL_POINTER_CODE: i32 = -101;
+
+fn null_pointer() -> i32 {
+    set_last_error("Null pointer argument", NULL_POINTER_CODE)
+}
+
+// catch_panic runs the body of an exported function and returns the sentinel
+// instead if it panics, as unwinding into the C caller is undefined behavior.
+fn catch_panic<T>(sentinel: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        set_last_error(format!("panic: {}", message), PANIC_CODE);
+        sentinel
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn gen_committer(
     chunk_size_in_scalars: u32,
 ) -> *const std::ffi::c_void {
-    let committer = Committer::new(chunk_size_in_scalars as usize);
-    return Box::into_raw(Box::new(committer)) as *const std::ffi::c_void;
+    catch_panic(ptr::null(), || {
+        if chunk_size_in_scalars == 0 {
+            set_last_error("The chunk size is zero", -1);
+            return ptr::null();
+        }
+        let committer = Committer::new(chunk_size_in_scalars as usize);
+        Box::into_raw(Box::new(committer)) as *const std::ffi::c_void
+    })
 }
 
 #[no_mangle]
@@ -17,12 +80,15 @@ pub extern "C" fn serialize_committer(
     out_ptr: *mut *mut u8,
     out_len: *mut usize,
 ) {
-    let committer = unsafe { &*(committer_ptr as *const Committer) };
-    let serialized = bincode::serialize(&committer).unwrap();
-    unsafe {
-        *out_len = serialized.len();
-        *out_ptr = Box::into_raw(serialized.into_boxed_slice()) as *mut u8;
-    }
+    catch_panic((), || {
+        if committer_ptr.is_null() || out_ptr.is_null() || out_len.is_null() {
+            null_pointer();
+            return;
+        }
+        let committer = unsafe { &*(committer_ptr as *const Committer) };
+        let serialized = bincode::serialize(&committer).unwrap();
+        export_bytes(serialized, out_ptr, out_len);
+    })
 }
 
 #[no_mangle]
@@ -30,17 +96,36 @@ pub extern "C" fn deserialize_committer(
     serialized_ptr: *const u8,
     serialized_len: usize,
 ) -> *const std::ffi::c_void {
-    let serialized =
-        unsafe { std::slice::from_raw_parts(serialized_ptr, serialized_len) };
+    catch_panic(ptr::null(), || {
+        if serialized_ptr.is_null() {
+            null_pointer();
+            return ptr::null();
+        }
+        let serialized = unsafe {
+            std::slice::from_raw_parts(serialized_ptr, serialized_len)
+        };
 
-    bincode::deserialize::<Committer>(&serialized)
-        .and_then(|c| Ok(Box::into_raw(Box::new(c)) as *const std::ffi::c_void))
-        .unwrap_or(ptr::null())
+        match Committer::from_bytes(serialized) {
+            Ok(c) => {
+                clear_last_error();
+                Box::into_raw(Box::new(c)) as *const std::ffi::c_void
+            }
+            Err(e) => {
+                set_last_error(e, -1);
+                ptr::null()
+            }
+        }
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn free_committer(committer_ptr: *const std::ffi::c_void) {
-    unsafe { drop(Box::from_raw(committer_ptr as *mut Committer)) }
+    if committer_ptr.is_null() {
+        return;
+    }
+    catch_panic((), || unsafe {
+        drop(Box::from_raw(committer_ptr as *mut Committer))
+    })
 }
 
 #[no_mangle]
@@ -48,9 +133,15 @@ pub extern "C" fn new_node(
     commiter: *const std::ffi::c_void,
     num_chunks: u32,
 ) -> *const std::ffi::c_void {
-    let commiter = unsafe { &*(commiter as *const Committer) };
-    let node = Node::new(commiter, num_chunks as usize);
-    return Box::into_raw(Box::new(node)) as *const std::ffi::c_void;
+    catch_panic(ptr::null(), || {
+        if commiter.is_null() {
+            null_pointer();
+            return ptr::null();
+        }
+        let commiter = unsafe { &*(commiter as *const Committer) };
+        let node = Node::new(commiter, num_chunks as usize);
+        Box::into_raw(Box::new(node)) as *const std::ffi::c_void
+    })
 }
 
 #[no_mangle]
@@ -60,17 +151,32 @@ pub extern "C" fn new_source_node(
     block_len: usize,
     num_chunks: u32,
 ) -> *const std::ffi::c_void {
-    let commiter = unsafe { &*(commiter as *const Committer) };
-    let block = unsafe { std::slice::from_raw_parts(block, block_len) };
-    if let Ok(node) = Node::new_source(commiter, block, num_chunks as usize) {
-        return Box::into_raw(Box::new(node)) as *const std::ffi::c_void;
-    }
-    ptr::null()
+    catch_panic(ptr::null(), || {
+        if commiter.is_null() || block.is_null() {
+            null_pointer();
+            return ptr::null();
+        }
+        let commiter = unsafe { &*(commiter as *const Committer) };
+        let block = unsafe { std::slice::from_raw_parts(block, block_len) };
+        match Node::new_source(commiter, block, num_chunks as usize) {
+            Ok(node) => {
+                clear_last_error();
+                Box::into_raw(Box::new(node)) as *const std::ffi::c_void
+            }
+            Err(e) => {
+                set_last_error(e, -1);
+                ptr::null()
+            }
+        }
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn free_node(node_ptr: *const std::ffi::c_void) {
-    unsafe { drop(Box::from_raw(node_ptr as *mut Node)) }
+    if node_ptr.is_null() {
+        return;
+    }
+    catch_panic((), || unsafe { drop(Box::from_raw(node_ptr as *mut Node)) })
 }
 
 #[no_mangle]
@@ -79,18 +185,22 @@ pub extern "C" fn send_chunk(
     out_data: *mut *mut u8,
     out_len: *mut usize,
 ) -> i32 {
-    let node = unsafe { &*(node_ptr as *const Node) };
-    if let Ok(serialized) = node.send().and_then(|message| {
-        bincode::serialize(&message).map_err(|e| e.to_string())
-    }) {
-        unsafe {
-            *out_len = serialized.len();
-            let boxed = serialized.into_boxed_slice();
-            *out_data = Box::into_raw(boxed) as *mut u8;
-        }
-        return 0;
-    }
-    -1
+    catch_panic(PANIC_CODE, || {
+        if node_ptr.is_null() || out_data.is_null() || out_len.is_null() {
+            return null_pointer();
+        }
+        let node = unsafe { &*(node_ptr as *const Node) };
+        match node.send().and_then(|message| {
+            bincode::serialize(&message).map_err(|e| e.to_string())
+        }) {
+            Ok(serialized) => {
+                clear_last_error();
+                export_bytes(serialized, out_data, out_len);
+                0
+            }
+            Err(e) => set_last_error(e, -1),
+        }
+    })
 }
 
 #[no_mangle]
@@ -99,29 +209,124 @@ pub extern "C" fn receive_chunk(
     chunk_start: *const u8,
     chunk_len: usize,
 ) -> i32 {
-    let node = unsafe { &mut *(node_ptr as *mut Node) };
-    let chunk = unsafe { std::slice::from_raw_parts(chunk_start, chunk_len) };
-
-    match bincode::deserialize(chunk).or(Err(-1)).and_then(|message| {
-        node.receive(message).map_err(|e| match e {
-            ReceiveError::ExistingCommitmentsMismatch(_e) => -2,
-            ReceiveError::ExistingChunksMismatch(_e) => -3,
-            ReceiveError::InvalidMessage(_e) => -4,
-            ReceiveError::LinearlyDependentChunk => -5,
-        })
-    }) {
-        Ok(_) => 0,
-        Err(e) => e,
-    }
+    catch_panic(PANIC_CODE, || {
+        if node_ptr.is_null() || chunk_start.is_null() {
+            return null_pointer();
+        }
+        let node = unsafe { &mut *(node_ptr as *mut Node) };
+        let chunk =
+            unsafe { std::slice::from_raw_parts(chunk_start, chunk_len) };
+
+        let message: Message = match bincode::deserialize(chunk) {
+            Ok(message) => message,
+            Err(e) => return set_last_error(e, -1),
+        };
+        match node.receive(message) {
+            Ok(()) => {
+                clear_last_error();
+                0
+            }
+            Err(e) => {
+                let code = match e {
+                    ReceiveError::ExistingCommitmentsMismatch(_) => -2,
+                    ReceiveError::ExistingChunksMismatch(_) => -3,
+                    ReceiveError::InvalidMessage(_) => -4,
+                    ReceiveError::LinearlyDependentChunk => -5,
+                    ReceiveError::HopLimitExceeded => -6,
+                };
+                set_last_error(e, code)
+            }
+        }
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn is_full(node_ptr: *const std::ffi::c_void) -> i32 {
-    let node = unsafe { &*(node_ptr as *const Node) };
-    if node.is_full() {
-        return 1;
-    }
-    return 0;
+    catch_panic(PANIC_CODE, || {
+        if node_ptr.is_null() {
+            return null_pointer();
+        }
+        let node = unsafe { &*(node_ptr as *const Node) };
+        if node.is_full() {
+            return 1;
+        }
+        0
+    })
+}
+
+// serialize_node serializes the node with Node::to_bytes, the buffer must be
+// released with free_buffer.
+#[no_mangle]
+pub extern "C" fn serialize_node(
+    node_ptr: *const std::ffi::c_void,
+    out_ptr: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    catch_panic(PANIC_CODE, || {
+        if node_ptr.is_null() || out_ptr.is_null() || out_len.is_null() {
+            return null_pointer();
+        }
+        let node = unsafe { &*(node_ptr as *const Node) };
+        clear_last_error();
+        export_bytes(node.to_bytes(), out_ptr, out_len);
+        0
+    })
+}
+
+// deserialize_node restores a node serialized with serialize_node, using the
+// committer it was created with. The committer must outlive the node.
+#[no_mangle]
+pub extern "C" fn deserialize_node(
+    committer_ptr: *const std::ffi::c_void,
+    serialized_ptr: *const u8,
+    serialized_len: usize,
+) -> *const std::ffi::c_void {
+    catch_panic(ptr::null(), || {
+        if committer_ptr.is_null() || serialized_ptr.is_null() {
+            null_pointer();
+            return ptr::null();
+        }
+        let committer = unsafe { &*(committer_ptr as *const Committer) };
+        let serialized = unsafe {
+            std::slice::from_raw_parts(serialized_ptr, serialized_len)
+        };
+        match Node::from_bytes(committer, serialized) {
+            Ok(node) => {
+                clear_last_error();
+                Box::into_raw(Box::new(node)) as *const std::ffi::c_void
+            }
+            Err(e) => {
+                set_last_error(e, -1);
+                ptr::null()
+            }
+        }
+    })
+}
+
+// node_rank returns the number of independent chunks the node holds, or -1
+// for a null handle.
+#[no_mangle]
+pub extern "C" fn node_rank(node_ptr: *const std::ffi::c_void) -> i32 {
+    catch_panic(PANIC_CODE, || {
+        if node_ptr.is_null() {
+            return set_last_error("Null pointer argument", -1);
+        }
+        let node = unsafe { &*(node_ptr as *const Node) };
+        node.rank() as i32
+    })
+}
+
+// node_num_chunks returns the number of chunks needed to decode, or -1 for a
+// null handle.
+#[no_mangle]
+pub extern "C" fn node_num_chunks(node_ptr: *const std::ffi::c_void) -> i32 {
+    catch_panic(PANIC_CODE, || {
+        if node_ptr.is_null() {
+            return set_last_error("Null pointer argument", -1);
+        }
+        let node = unsafe { &*(node_ptr as *const Node) };
+        node.num_chunks() as i32
+    })
 }
 
 #[no_mangle]
@@ -130,27 +335,90 @@ pub extern "C" fn decode(
     out_data: *mut *mut u8,
     out_len: *mut usize,
 ) -> i32 {
-    let node = unsafe { &*(node_ptr as *const Node) };
-    if !node.is_full() {
-        return -1;
-    }
+    catch_panic(PANIC_CODE, || {
+        if node_ptr.is_null() || out_data.is_null() || out_len.is_null() {
+            return null_pointer();
+        }
+        let node = unsafe { &*(node_ptr as *const Node) };
+        if !node.is_full() {
+            return set_last_error("The node is not full", -1);
+        }
+
+        match node.decode() {
+            Ok(data) => {
+                clear_last_error();
+                export_bytes(data, out_data, out_len);
+                0
+            }
+            Err(e) => set_last_error(e, -2),
+        }
+    })
+}
 
-    if let Ok(data) = node.decode() {
-        unsafe {
-            *out_len = data.len();
-            *out_data = Box::into_raw(data.into_boxed_slice()) as *mut u8;
+// decode_len returns the number of bytes decode produces, or -1 if the node is
+// not full.
+#[no_mangle]
+pub extern "C" fn decode_len(node_ptr: *const std::ffi::c_void) -> i64 {
+    catch_panic(PANIC_CODE as i64, || {
+        if node_ptr.is_null() {
+            return null_pointer() as i64;
         }
-        return 0;
-    }
-    return 0;
+        let node = unsafe { &*(node_ptr as *const Node) };
+        match node.chunk_size_scalars() {
+            Some(scalars) if node.is_full() => {
+                clear_last_error();
+                (node.num_chunks() * max_chunk_len(scalars)) as i64
+            }
+            _ => set_last_error("The node is not full", -1) as i64,
+        }
+    })
+}
+
+// decode_into decodes the block into a buffer owned by the caller, of at
+// least decode_len bytes. It returns -1 if the node is not full, -2 if the
+// decoding fails and -3 if the buffer is too small.
+#[no_mangle]
+pub extern "C" fn decode_into(
+    node_ptr: *const std::ffi::c_void,
+    buf: *mut u8,
+    buf_len: usize,
+) -> i32 {
+    catch_panic(PANIC_CODE, || {
+        if node_ptr.is_null() || buf.is_null() {
+            return null_pointer();
+        }
+        let len = decode_len(node_ptr);
+        if len < 0 {
+            return len as i32;
+        }
+        if buf_len < len as usize {
+            return set_last_error(
+                format!("The buffer needs {} bytes, got {}", len, buf_len),
+                -3,
+            );
+        }
+        let node = unsafe { &*(node_ptr as *const Node) };
+        let mut out = unsafe { std::slice::from_raw_parts_mut(buf, buf_len) };
+        match node.decode_to(&mut out) {
+            Ok(()) => {
+                clear_last_error();
+                0
+            }
+            Err(e) => set_last_error(e, -2),
+        }
+    })
 }
 
+// free_buffer releases a buffer returned by any of the functions above.
 #[no_mangle]
 pub extern "C" fn free_buffer(ptr: *mut u8, len: usize) {
-    unsafe {
-        let slice = std::slice::from_raw_parts_mut(ptr, len);
-        drop(Box::from_raw(slice));
+    if ptr.is_null() {
+        return;
     }
+    catch_panic((), || unsafe {
+        let slice = ptr::slice_from_raw_parts_mut(ptr, len);
+        drop(Box::from_raw(slice));
+    })
 }
 
 #[no_mangle]
@@ -160,18 +428,361 @@ pub extern "C" fn commitments_hash(
     out_ptr: *mut *mut u8,
     out_len: *mut usize,
 ) -> i32 {
-    let message_bytes =
-        unsafe { std::slice::from_raw_parts(message_data, message_len) };
-    match bincode::deserialize::<Message>(&message_bytes) {
-        Ok(message) => {
-            let hash = message.commitments_hash();
-            unsafe {
-                *out_len = hash.len();
-                *out_ptr =
-                    Box::into_raw(hash.to_vec().into_boxed_slice()) as *mut u8;
-            }
-            return 0;
-        }
-        Err(_) => return -1,
+    catch_panic(PANIC_CODE, || {
+        if message_data.is_null() || out_ptr.is_null() || out_len.is_null() {
+            return null_pointer();
+        }
+        let message_bytes =
+            unsafe { std::slice::from_raw_parts(message_data, message_len) };
+        match bincode::deserialize::<Message>(message_bytes) {
+            Ok(message) => {
+                clear_last_error();
+                export_bytes(
+                    message.commitments_hash().to_vec(),
+                    out_ptr,
+                    out_len,
+                );
+                0
+            }
+            Err(e) => set_last_error(e, -1),
+        }
+    })
+}
+
+// get_last_error copies the message of the last failing call on this thread
+// into a buffer to release with free_buffer. It returns -1, leaving the out
+// params untouched, if the last call succeeded.
+#[no_mangle]
+pub extern "C" fn get_last_error(
+    out_ptr: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    catch_panic(PANIC_CODE, || {
+        if out_ptr.is_null() || out_len.is_null() {
+            return NULL_POINTER_CODE;
+        }
+        match LAST_ERROR.with(|e| e.borrow().clone()) {
+            Some(error) => {
+                export_bytes(error.into_bytes(), out_ptr, out_len);
+                0
+            }
+            None => -1,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::random_u8_slice;
+
+    // exported calls f with fresh out params and returns the exported buffer.
+    fn exported(f: impl FnOnce(*mut *mut u8, *mut usize)) -> (*mut u8, usize) {
+        let mut out_ptr: *mut u8 = ptr::null_mut();
+        let mut out_len: usize = 0;
+        f(&mut out_ptr, &mut out_len);
+        assert!(!out_ptr.is_null());
+        (out_ptr, out_len)
+    }
+
+    #[test]
+    fn test_exported_buffers() {
+        let num_chunks = 2;
+        let committer = gen_committer(2);
+        let block = random_u8_slice(num_chunks * 32);
+        let source = new_source_node(committer, block.as_ptr(), block.len(), 2);
+        let sink = new_node(committer, 2);
+        assert_eq!(is_full(sink), 0);
+
+        let (ptr, len) = exported(|p, l| serialize_committer(committer, p, l));
+        let copy = deserialize_committer(ptr, len);
+        assert!(!copy.is_null());
+        free_committer(copy);
+        free_buffer(ptr, len);
+
+        while is_full(sink) == 0 {
+            let (ptr, len) =
+                exported(|p, l| assert_eq!(send_chunk(source, p, l), 0));
+            let (hash, hash_len) = exported(|p, l| {
+                assert_eq!(commitments_hash(ptr, len, p, l), 0)
+            });
+            assert_eq!(hash_len, 32);
+            free_buffer(hash, hash_len);
+            receive_chunk(sink, ptr, len);
+            free_buffer(ptr, len);
+        }
+
+        // A message with a malformed point is rejected when deserialized.
+        let (ptr, len) =
+            exported(|p, l| assert_eq!(send_chunk(source, p, l), 0));
+        let mut bad = unsafe { std::slice::from_raw_parts(ptr, len) }.to_vec();
+        free_buffer(ptr, len);
+        bad[len - 36..len - 4].fill(0xff);
+        assert_eq!(receive_chunk(sink, bad.as_ptr(), bad.len()), -1);
+
+        let (ptr, len) = exported(|p, l| assert_eq!(decode(sink, p, l), 0));
+        assert_eq!(unsafe { std::slice::from_raw_parts(ptr, len) }, &block[..]);
+        free_buffer(ptr, len);
+        free_buffer(ptr::null_mut(), 0);
+
+        free_node(source);
+        free_node(sink);
+        free_committer(committer);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        use crate::node::CommitmentHeader;
+        use curve25519_dalek::Scalar;
+
+        let committer = Committer::new(1);
+        let committer_ptr = &committer as *const Committer as *const _;
+        let mut out_ptr: *mut u8 = ptr::null_mut();
+        let mut out_len: usize = 0;
+        let node = new_node(committer_ptr, 1);
+        assert_eq!(decode(node, &mut out_ptr, &mut out_len), -1);
+        free_node(node);
+
+        // A single scalar chunk is valid but doesn't unpack to bytes, the
+        // full node fails to decode it.
+        let data = vec![Scalar::from(5u8)];
+        let header =
+            CommitmentHeader::new(vec![committer.commit(&data).unwrap()]);
+        let message =
+            Message::from_header_and_chunk(&header, data, vec![Scalar::ONE])
+                .unwrap();
+        let mut node = Node::new(&committer, 1);
+        node.receive(message).unwrap();
+        let node = Box::into_raw(Box::new(node)) as *const std::ffi::c_void;
+        assert_eq!(is_full(node), 1);
+        assert_eq!(decode(node, &mut out_ptr, &mut out_len), -2);
+        assert!(out_ptr.is_null());
+        assert_eq!(out_len, 0);
+        free_node(node);
+    }
+
+    #[test]
+    fn test_catch_panic() {
+        let code = catch_panic(PANIC_CODE, || -> i32 { panic!("boom") });
+        assert_eq!(code, PANIC_CODE);
+        let mut out_ptr: *mut u8 = ptr::null_mut();
+        let mut out_len: usize = 0;
+        assert_eq!(get_last_error(&mut out_ptr, &mut out_len), 0);
+        assert_eq!(
+            unsafe { std::slice::from_raw_parts(out_ptr, out_len) },
+            b"panic: boom"
+        );
+        free_buffer(out_ptr, out_len);
+        assert!(catch_panic(ptr::null::<u8>(), || panic!("boom")).is_null());
+        assert_eq!(catch_panic(0, || 7), 7);
+    }
+
+    #[test]
+    fn test_null_pointers() {
+        let null = ptr::null::<std::ffi::c_void>();
+        let mut out_ptr: *mut u8 = ptr::null_mut();
+        let mut out_len: usize = 0;
+        let committer = gen_committer(2);
+        let node = new_node(committer, 2);
+
+        assert!(new_node(null, 2).is_null());
+        assert!(new_source_node(null, [0u8; 64].as_ptr(), 64, 2).is_null());
+        assert!(new_source_node(committer, ptr::null(), 0, 2).is_null());
+        assert!(deserialize_committer(ptr::null(), 0).is_null());
+        assert!(gen_committer(0).is_null());
+        serialize_committer(null, &mut out_ptr, &mut out_len);
+        serialize_committer(committer, ptr::null_mut(), &mut out_len);
+        assert!(out_ptr.is_null());
+        assert_eq!(
+            send_chunk(null, &mut out_ptr, &mut out_len),
+            NULL_POINTER_CODE
+        );
+        assert_eq!(
+            send_chunk(node, ptr::null_mut(), &mut out_len),
+            NULL_POINTER_CODE
+        );
+        assert_eq!(receive_chunk(null, [0u8].as_ptr(), 1), NULL_POINTER_CODE);
+        assert_eq!(receive_chunk(node, ptr::null(), 0), NULL_POINTER_CODE);
+        assert_eq!(is_full(null), NULL_POINTER_CODE);
+        assert_eq!(decode(null, &mut out_ptr, &mut out_len), NULL_POINTER_CODE);
+        assert_eq!(
+            decode(node, &mut out_ptr, ptr::null_mut()),
+            NULL_POINTER_CODE
+        );
+        assert_eq!(
+            commitments_hash(ptr::null(), 0, &mut out_ptr, &mut out_len),
+            NULL_POINTER_CODE
+        );
+        assert_eq!(
+            get_last_error(ptr::null_mut(), ptr::null_mut()),
+            NULL_POINTER_CODE
+        );
+        assert_eq!(get_last_error(&mut out_ptr, &mut out_len), 0);
+        assert_eq!(
+            unsafe { std::slice::from_raw_parts(out_ptr, out_len) },
+            b"Null pointer argument"
+        );
+        free_buffer(out_ptr, out_len);
+        free_node(null);
+        free_committer(null);
+
+        free_node(node);
+        free_committer(committer);
+    }
+
+    #[test]
+    fn test_node_rank() {
+        let num_chunks = 4;
+        let committer = gen_committer(2);
+        let block = random_u8_slice(num_chunks * 32);
+        let source = new_source_node(committer, block.as_ptr(), block.len(), 4);
+        let sink = new_node(committer, 4);
+        assert_eq!(node_rank(source), 4);
+        assert_eq!(node_num_chunks(source), 4);
+        assert_eq!(node_rank(sink), 0);
+        assert_eq!(node_num_chunks(sink), 4);
+
+        let mut rank = 0;
+        while is_full(sink) == 0 {
+            let (ptr, len) =
+                exported(|p, l| assert_eq!(send_chunk(source, p, l), 0));
+            if receive_chunk(sink, ptr, len) == 0 {
+                rank += 1;
+            }
+            free_buffer(ptr, len);
+            assert_eq!(node_rank(sink), rank);
+            assert_eq!(node_num_chunks(sink), 4);
+        }
+        assert_eq!(rank, 4);
+        assert_eq!(node_rank(ptr::null()), -1);
+        assert_eq!(node_num_chunks(ptr::null()), -1);
+
+        free_node(source);
+        free_node(sink);
+        free_committer(committer);
+    }
+
+    #[test]
+    fn test_serialize_node() {
+        let num_chunks = 3;
+        let committer = gen_committer(2);
+        let block = random_u8_slice(num_chunks * 32);
+        let source = new_source_node(committer, block.as_ptr(), block.len(), 3);
+        let sink = new_node(committer, 3);
+        while node_rank(sink) < 2 {
+            let (ptr, len) =
+                exported(|p, l| assert_eq!(send_chunk(source, p, l), 0));
+            receive_chunk(sink, ptr, len);
+            free_buffer(ptr, len);
+        }
+
+        let (ptr, len) =
+            exported(|p, l| assert_eq!(serialize_node(sink, p, l), 0));
+        let restored = deserialize_node(committer, ptr, len);
+        assert!(!restored.is_null());
+        assert!(deserialize_node(committer, ptr, len - 1).is_null());
+        free_buffer(ptr, len);
+        assert_eq!(node_rank(restored), 2);
+        while is_full(restored) == 0 {
+            let (ptr, len) =
+                exported(|p, l| assert_eq!(send_chunk(source, p, l), 0));
+            receive_chunk(restored, ptr, len);
+            free_buffer(ptr, len);
+        }
+        let (ptr, len) = exported(|p, l| assert_eq!(decode(restored, p, l), 0));
+        assert_eq!(unsafe { std::slice::from_raw_parts(ptr, len) }, &block[..]);
+        free_buffer(ptr, len);
+
+        free_node(source);
+        free_node(sink);
+        free_node(restored);
+        free_committer(committer);
+    }
+
+    #[test]
+    fn test_decode_into() {
+        let num_chunks = 3;
+        let committer = gen_committer(4);
+        // Chunks of 3 words, 4 scalars each.
+        let block = random_u8_slice(num_chunks * 96);
+        let source = new_source_node(committer, block.as_ptr(), block.len(), 3);
+        let sink = new_node(committer, 3);
+        let mut buf = vec![0u8; 400];
+        assert_eq!(decode_len(sink), -1);
+        assert_eq!(decode_into(sink, buf.as_mut_ptr(), buf.len()), -1);
+        while is_full(sink) == 0 {
+            let (ptr, len) =
+                exported(|p, l| assert_eq!(send_chunk(source, p, l), 0));
+            receive_chunk(sink, ptr, len);
+            free_buffer(ptr, len);
+        }
+
+        let (ptr, len) = exported(|p, l| assert_eq!(decode(sink, p, l), 0));
+        assert_eq!(decode_len(sink), len as i64);
+        assert_eq!(decode_len(source), len as i64);
+        free_buffer(ptr, len);
+        assert_eq!(decode_into(sink, buf.as_mut_ptr(), len - 1), -3);
+        assert_eq!(decode_into(sink, buf.as_mut_ptr(), buf.len()), 0);
+        assert_eq!(buf[..len], block[..]);
+        assert_eq!(decode_len(ptr::null()), NULL_POINTER_CODE as i64);
+
+        free_node(source);
+        free_node(sink);
+        free_committer(committer);
+    }
+
+    #[test]
+    fn test_get_last_error() {
+        let last_error = || {
+            let mut out_ptr: *mut u8 = ptr::null_mut();
+            let mut out_len: usize = 0;
+            if get_last_error(&mut out_ptr, &mut out_len) != 0 {
+                return None;
+            }
+            let error = String::from_utf8(
+                unsafe { std::slice::from_raw_parts(out_ptr, out_len) }
+                    .to_vec(),
+            )
+            .unwrap();
+            free_buffer(out_ptr, out_len);
+            Some(error)
+        };
+        let committer = gen_committer(2);
+        let first = random_u8_slice(2 * 32);
+        let second = random_u8_slice(2 * 32);
+        let source = new_source_node(committer, first.as_ptr(), 64, 2);
+        let other = new_source_node(committer, second.as_ptr(), 64, 2);
+        let sink = new_node(committer, 2);
+
+        let (ptr, len) =
+            exported(|p, l| assert_eq!(send_chunk(source, p, l), 0));
+        assert_eq!(receive_chunk(sink, ptr, len), 0);
+        free_buffer(ptr, len);
+        assert_eq!(last_error(), None);
+
+        // A message of another block doesn't match the commitments.
+        let (ptr, len) =
+            exported(|p, l| assert_eq!(send_chunk(other, p, l), 0));
+        assert_eq!(receive_chunk(sink, ptr, len), -2);
+        free_buffer(ptr, len);
+        assert_eq!(
+            last_error().unwrap(),
+            "commitments mismatch: The commitments do not match"
+        );
+        assert_eq!(is_full(sink), 0);
+        let mut out_ptr: *mut u8 = ptr::null_mut();
+        let mut out_len: usize = 0;
+        assert_eq!(decode(sink, &mut out_ptr, &mut out_len), -1);
+        assert_eq!(last_error().unwrap(), "The node is not full");
+
+        let (ptr, len) =
+            exported(|p, l| assert_eq!(send_chunk(source, p, l), 0));
+        free_buffer(ptr, len);
+        assert_eq!(last_error(), None);
+
+        free_node(source);
+        free_node(other);
+        free_node(sink);
+        free_committer(committer);
     }
 }
@@ -0,0 +1,101 @@
+use crate::blocks::{max_chunk_len, BlockError, Committer, PADDING_HEADER_LEN};
+use crate::node::Node;
+
+// generation_capacity returns how many bytes of the file fit in a generation of
+// chunks_per_gen chunks as large as the committer allows.
+fn generation_capacity(
+    chunks_per_gen: usize,
+    committer: &Committer,
+) -> Result<usize, String> {
+    if chunks_per_gen == 0 {
+        return Err(BlockError::ZeroChunks.into());
+    }
+    // Every generation is padded with its length, see pad_block.
+    let capacity = chunks_per_gen * max_chunk_len(committer.len());
+    if capacity <= PADDING_HEADER_LEN {
+        return Err(
+            "The committer is too small to hold a generation".to_string()
+        );
+    }
+    Ok(capacity - PADDING_HEADER_LEN)
+}
+
+// split_into_generations splits the data in generations of chunks_per_gen
+// chunks and returns a source node for each one. Every generation is padded
+// with its length, so the last one may be partially filled.
+pub fn split_into_generations<'a>(
+    data: &[u8],
+    chunks_per_gen: usize,
+    committer: &'a Committer,
+) -> Result<Vec<Node<'a>>, String> {
+    let capacity = generation_capacity(chunks_per_gen, committer)?;
+    if data.is_empty() {
+        return Ok(vec![Node::new_source_padded(
+            committer,
+            data,
+            chunks_per_gen,
+        )?]);
+    }
+    data.chunks(capacity)
+        .map(|generation| {
+            Node::new_source_padded(committer, generation, chunks_per_gen)
+        })
+        .collect()
+}
+
+// reassemble decodes every generation, in order, and returns the original
+// data.
+pub fn reassemble(nodes: &[Node]) -> Result<Vec<u8>, String> {
+    let mut ret = Vec::new();
+    for node in nodes {
+        ret.extend_from_slice(&node.decode_exact()?);
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::random_u8_slice;
+    use crate::node::ReceiveError;
+
+    #[test]
+    fn test_file_roundtrip() {
+        let committer = Committer::new(5);
+        let chunks_per_gen = 4;
+        // Three full generations and a partial one.
+        let capacity = generation_capacity(chunks_per_gen, &committer).unwrap();
+        let data = random_u8_slice(capacity * 3 + 100);
+        let sources =
+            split_into_generations(&data, chunks_per_gen, &committer).unwrap();
+        assert_eq!(sources.len(), 4);
+
+        let mut sinks: Vec<Node> = Vec::with_capacity(sources.len());
+        for source in &sources {
+            let mut sink = Node::new(&committer, chunks_per_gen);
+            while !sink.is_full() {
+                match sink.receive(source.send().unwrap()) {
+                    Ok(()) | Err(ReceiveError::LinearlyDependentChunk) => {}
+                    Err(e) => panic!("Unexpected error: {:?}", e),
+                }
+            }
+            sinks.push(sink);
+        }
+        assert_eq!(reassemble(&sinks).unwrap(), data);
+
+        assert!(reassemble(&[Node::new(&committer, chunks_per_gen)]).is_err());
+        assert!(split_into_generations(&data, 1, &Committer::new(1)).is_err());
+        assert_eq!(
+            split_into_generations(&data, 0, &committer).err(),
+            Some("The number of chunks must be positive".to_string())
+        );
+    }
+
+    #[test]
+    fn test_empty_file() {
+        let committer = Committer::new(5);
+        let sources = split_into_generations(&[], 4, &committer).unwrap();
+        assert_eq!(sources.len(), 1);
+        assert!(reassemble(&sources).unwrap().is_empty());
+    }
+}
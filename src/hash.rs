@@ -0,0 +1,38 @@
+// Hash function of the commitment and generator set hashes. SHA-256 by
+// default, the sha3 and blake3 features select SHA3 or BLAKE3 instead, blake3
+// wins if both are enabled. The generators themselves are always derived with
+// SHA-512, see blocks::generators_from_seed, so that committers built from the
+// same seed agree whatever the features.
+
+#[cfg(not(any(feature = "sha3", feature = "blake3")))]
+use sha2::{Digest, Sha256};
+#[cfg(all(feature = "sha3", not(feature = "blake3")))]
+use sha3::{Digest, Sha3_256};
+
+// digest returns a 32 byte hash of the data.
+pub fn digest(data: &[u8]) -> [u8; 32] {
+    #[cfg(not(any(feature = "sha3", feature = "blake3")))]
+    return Sha256::digest(data).into();
+    #[cfg(all(feature = "sha3", not(feature = "blake3")))]
+    return Sha3_256::digest(data).into();
+    #[cfg(feature = "blake3")]
+    return blake3::hash(data).into();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::Sha256;
+
+    #[test]
+    fn test_digest() {
+        let data = b"rlnc";
+        assert_eq!(digest(data), digest(data));
+        assert_ne!(digest(data), digest(b"rlnd"));
+        let sha256: [u8; 32] = <Sha256 as sha2::Digest>::digest(data).into();
+        assert_eq!(
+            digest(data) == sha256,
+            cfg!(not(any(feature = "sha3", feature = "blake3")))
+        );
+    }
+}
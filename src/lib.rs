@@ -1,4 +1,6 @@
 pub mod blocks;
 pub mod c_api;
+pub mod file;
+pub mod hash;
 pub mod matrix;
 pub mod node;
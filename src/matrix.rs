@@ -1,4 +1,11 @@
+use crate::blocks::scalar_to_u64;
 use curve25519_dalek::Scalar;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub mod bareiss;
+
 /*
 Echelon is a structure that keeps both the echelon form of a matrix and the transoformations
 necessary to obtain these form. Self consistency
@@ -12,15 +19,20 @@ where A is the transformation matrix for the first i-1 messages and I is the ide
 for the rest of the rows.
 
 We could in principle work with smaller integer matrices like u64 instead of Scalar, but care
-needs to be taken to prevent the integers to grow with the number of rows. Implementing something
-like Bareiss' seems overkill at this stage.
+needs to be taken to prevent the integers to grow with the number of rows. The bareiss module
+implements this with i128 entries for benchmarking purposes.
 */
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Echelon {
     coefficients: Vec<Vec<Scalar>>,
     echelon: Vec<Vec<Scalar>>,
     transform: Vec<Vec<Scalar>>,
 }
 
+// Eschelon is the previous, misspelled, name of Echelon.
+#[deprecated(note = "use Echelon")]
+pub type Eschelon = Echelon;
+
 impl Echelon {
     pub fn new(size: usize) -> Self {
         let mut transform = vec![vec![Scalar::ZERO; size]; size];
@@ -48,12 +60,76 @@ impl Echelon {
 
     // is_full returns if the echelon form is square.
     pub fn is_full(&self) -> bool {
-        if self.coefficients.len() == 0 {
+        if self.coefficients.is_empty() {
             return false;
         }
         self.coefficients.len() == self.coefficients[0].len()
     }
 
+    // rank returns the number of linearly independent rows added so far.
+    pub fn rank(&self) -> usize {
+        self.coefficients.len()
+    }
+
+    // size returns the number of columns, that is the rank of a full matrix.
+    pub fn size(&self) -> usize {
+        self.transform.len()
+    }
+
+    // coefficients returns the independent rows in the order they were added.
+    pub fn coefficients(&self) -> &Vec<Vec<Scalar>> {
+        &self.coefficients
+    }
+
+    // pivot_columns returns the column of the leading entry of each echelon
+    // row, in increasing order. The source chunks at the pivot columns are the
+    // ones whose coefficients are already part of the echelon form.
+    pub fn pivot_columns(&self) -> Vec<usize> {
+        self.echelon
+            .iter()
+            .map(|row| first_entry(row).unwrap())
+            .collect()
+    }
+
+    // has_valid_shape checks that transform is square, that there are at most
+    // as many coefficient and echelon rows as columns and that every row has
+    // one entry per column. verify_invariant indexes the matrices assuming
+    // so, deserialized ones have to be checked first.
+    pub fn has_valid_shape(&self) -> bool {
+        let size = self.size();
+        self.coefficients.len() <= size
+            && self.echelon.len() <= size
+            && self
+                .transform
+                .iter()
+                .chain(self.coefficients.iter())
+                .chain(self.echelon.iter())
+                .all(|row| row.len() == size)
+    }
+
+    // verify_invariant checks that transform * coefficients = echelon for the
+    // rows added so far and that the pivots of the echelon rows are strictly
+    // increasing. It is meant for tests and debugging.
+    pub fn verify_invariant(&self) -> bool {
+        let rank = self.rank();
+        if self.echelon.len() != rank {
+            return false;
+        }
+        let consistent = (0..rank).all(|i| {
+            (0..self.size()).all(|k| {
+                (0..rank)
+                    .map(|j| self.transform[i][j] * self.coefficients[j][k])
+                    .sum::<Scalar>()
+                    == self.echelon[i][k]
+            })
+        });
+        let pivots: Vec<Option<usize>> =
+            self.echelon.iter().map(|row| first_entry(row)).collect();
+        consistent
+            && pivots.iter().all(|p| p.is_some())
+            && pivots.windows(2).all(|w| w[0] < w[1])
+    }
+
     // add_row adds a row to the coefficients matrix and updates the echelon form and the transform.
     // It returns false if the row is linearly dependent with the previous ones.
     pub fn add_row(&mut self, row: Vec<Scalar>) -> bool {
@@ -65,16 +141,14 @@ impl Echelon {
             return false;
         }
         if current_size == 0 {
-            self.echelon
-                .push(row.iter().map(|x| Scalar::from(*x)).collect());
+            self.echelon.push(row.clone());
             self.coefficients.push(row);
             return true;
         }
         let mut tr = self.transform[current_size].clone();
         let mut i = 0;
         let mut j: usize;
-        let mut new_echelon_row: Vec<Scalar> =
-            row.iter().map(|x| Scalar::from(*x)).collect();
+        let mut new_echelon_row: Vec<Scalar> = row.clone();
         while i < current_size {
             j = first_entry(&self.echelon[i]).unwrap();
             let k = match first_entry(&new_echelon_row) {
@@ -110,7 +184,98 @@ impl Echelon {
             return true;
         }
         self.transform[i] = tr;
-        return true;
+        true
+    }
+
+    // would_be_independent returns whether add_row would accept the row,
+    // without modifying the echelon.
+    pub fn would_be_independent(&self, row: &[Scalar]) -> bool {
+        if self.coefficients.len() == row.len() {
+            return false;
+        }
+        let mut reduced = row.to_vec();
+        for echelon_row in self.echelon.iter() {
+            let j = first_entry(echelon_row).unwrap();
+            let f = reduced[j];
+            if f == Scalar::ZERO {
+                continue;
+            }
+            let pivot = echelon_row[j];
+            reduced
+                .iter_mut()
+                .zip(echelon_row.iter())
+                .for_each(|(x, y)| *x = pivot * (*x) - y * f);
+        }
+        first_entry(&reduced).is_some()
+    }
+
+    // rref returns the reduced row echelon form of the coefficients, with the
+    // pivots normalized to one, together with the transform such that
+    // transform * coefficients = rref. Both have one row per independent row
+    // received, the transform is indexed by the coefficients in the order they
+    // were added.
+    pub fn rref(&self) -> (Vec<Vec<Scalar>>, Vec<Vec<Scalar>>) {
+        let rank = self.rank();
+        let pivots = self.pivot_columns();
+        let mut rref = self.echelon.clone();
+        let mut transform: Vec<Vec<Scalar>> = self.transform[..rank]
+            .iter()
+            .map(|row| row[..rank].to_vec())
+            .collect();
+        let mut inverses: Vec<Scalar> =
+            (0..rank).map(|i| rref[i][pivots[i]]).collect();
+        Scalar::batch_invert(&mut inverses);
+        for i in (0..rank).rev() {
+            rref[i].iter_mut().for_each(|x| *x *= inverses[i]);
+            transform[i].iter_mut().for_each(|x| *x *= inverses[i]);
+            let (rref_head, rref_tail) = rref.split_at_mut(i);
+            let (transform_head, transform_tail) = transform.split_at_mut(i);
+            for (rref_row, transform_row) in
+                rref_head.iter_mut().zip(transform_head.iter_mut())
+            {
+                let f = rref_row[pivots[i]];
+                if f == Scalar::ZERO {
+                    continue;
+                }
+                rref_row
+                    .iter_mut()
+                    .zip(rref_tail[0].iter())
+                    .for_each(|(x, y)| *x -= y * f);
+                transform_row
+                    .iter_mut()
+                    .zip(transform_tail[0].iter())
+                    .for_each(|(x, y)| *x -= y * f);
+            }
+        }
+        (rref, transform)
+    }
+
+    // solve returns the combination of the coefficients, in the order they
+    // were added, that equals the unit vector of the target column. The
+    // matrix need not be full, only the unit vector has to be in the span of
+    // the rows, in which case it is a row of the reduced echelon form.
+    pub fn solve(&self, target: usize) -> Result<Vec<Scalar>, String> {
+        if target >= self.size() {
+            return Err(format!(
+                "Target {} out of range for size {}",
+                target,
+                self.size()
+            ));
+        }
+        let (rref, mut transform) = self.rref();
+        let i = self
+            .pivot_columns()
+            .iter()
+            .position(|&j| j == target)
+            .ok_or("The target is not in the span of the rows")?;
+        if rref[i]
+            .iter()
+            .enumerate()
+            .any(|(j, x)| j != target && *x != Scalar::ZERO)
+        {
+            return Err("The target is not in the span of the rows".to_string());
+        }
+        Ok(transform.swap_remove(i))
     }
 
     // compound_scalars performs a matrix multiplications. The node coefficients are kept as u32
@@ -128,23 +293,120 @@ impl Echelon {
             .collect()
     }
 
+    // compound_scalars_u32 is compound_scalars with the coefficients held as
+    // u32, as the comment above intends. It fails when a coefficient does not
+    // fit, which happens after too many hops, instead of silently carrying on
+    // with larger scalars.
+    pub fn compound_scalars_u32(
+        &self,
+        scalars: &[u8],
+    ) -> Result<Vec<u32>, String> {
+        let coefficients: Vec<Vec<u32>> = self
+            .coefficients
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|x| {
+                        scalar_to_u64(x)
+                            .and_then(|x| u32::try_from(x).ok())
+                            .ok_or("A coefficient does not fit in a u32")
+                    })
+                    .collect::<Result<_, _>>()
+            })
+            .collect::<Result<_, _>>()?;
+        (0..self.transform.len())
+            .map(|j| {
+                scalars.iter().zip(coefficients.iter()).try_fold(
+                    0u32,
+                    |sum, (x, coeffs)| {
+                        (*x as u32)
+                            .checked_mul(coeffs[j])
+                            .and_then(|y| sum.checked_add(y))
+                            .ok_or_else(|| {
+                                format!("Coefficient {} overflows a u32", j)
+                            })
+                    },
+                )
+            })
+            .collect()
+    }
+
+    // inverse returns the inverse of the coefficient matrix. With the ct-decode
+    // feature it runs in time independent of the matrix entries, see
+    // inverse_ct, otherwise it skips the work for zero entries.
     pub fn inverse(&self) -> Result<Vec<Vec<Scalar>>, String> {
+        #[cfg(feature = "ct-decode")]
+        return self.inverse_ct();
+        #[cfg(not(feature = "ct-decode"))]
+        return self.inverse_vartime();
+    }
+
+    // inverse_vartime back substitutes skipping the rows whose multiplier is
+    // zero, its running time leaks the number of zero entries.
+    pub fn inverse_vartime(&self) -> Result<Vec<Vec<Scalar>>, String> {
+        self.back_substitute(false, cfg!(feature = "parallel"))
+    }
+
+    // inverse_ct performs the same field operations for every matrix of a
+    // given size. Scalar arithmetic and Scalar::invert are constant time in
+    // curve25519-dalek. The remaining steps that are not constant time are
+    // add_row, whose elimination branches on the coefficients, and the size
+    // and shape checks here. This only hides the coefficient matrix, which is
+    // public when it travels with every message. The decoded data never goes
+    // through the inverse: it is multiplied by it in Node::decode, which does
+    // the same operations whatever the data with or without this feature.
+    pub fn inverse_ct(&self) -> Result<Vec<Vec<Scalar>>, String> {
+        self.back_substitute(true, cfg!(feature = "parallel"))
+    }
+
+    // back_substitute computes the inverse from the echelon form. With
+    // parallel set, the columns of each row of the inverse are updated
+    // independently, across threads when built with the parallel feature.
+    fn back_substitute(
+        &self,
+        constant_time: bool,
+        parallel: bool,
+    ) -> Result<Vec<Vec<Scalar>>, String> {
         if self.coefficients.is_empty() {
             return Err("No coefficients to decode".to_string());
         }
         if self.echelon.len() != self.coefficients[0].len() {
             return Err("The echelon form is not square".to_string());
         }
+        // Invert all the pivots at once, with a single field inversion.
+        let mut pivots: Vec<Scalar> = (0..self.echelon.len())
+            .map(|i| self.echelon[i][i])
+            .collect();
+        Scalar::batch_invert(&mut pivots);
         let mut inverse = self.transform.clone();
         for i in (0..self.echelon.len()).rev() {
-            let pivot = self.echelon[i][i].invert();
-            inverse[i].iter_mut().for_each(|x| *x = *x * pivot);
+            let pivot = pivots[i];
+            inverse[i].iter_mut().for_each(|x| *x *= pivot);
+            if parallel {
+                let diffs: Vec<(usize, Scalar)> = ((i + 1)..self.echelon.len())
+                    .map(|j| (j - i - 1, self.echelon[i][j] * pivot))
+                    .filter(|(_, diff)| constant_time || *diff != Scalar::ZERO)
+                    .collect();
+                let (head, tail) = inverse.split_at_mut(i + 1);
+                let update = |(k, x): (usize, &mut Scalar)| {
+                    diffs.iter().for_each(|(j, diff)| *x -= tail[*j][k] * diff)
+                };
+                #[cfg(feature = "parallel")]
+                head[i].par_iter_mut().enumerate().for_each(update);
+                #[cfg(not(feature = "parallel"))]
+                head[i].iter_mut().enumerate().for_each(update);
+                continue;
+            }
             for j in (i + 1)..self.echelon.len() {
                 let diff = self.echelon[i][j] * pivot;
-                for k in 0..self.echelon.len() {
-                    let actual_diff = inverse[j][k] * diff;
-                    inverse[i][k] -= actual_diff;
+                if !constant_time && diff == Scalar::ZERO {
+                    continue;
                 }
+                let (head, tail) = inverse.split_at_mut(j);
+                head[i]
+                    .iter_mut()
+                    .zip(tail[0].iter())
+                    .for_each(|(x, y)| *x -= y * diff);
             }
         }
         Ok(inverse)
@@ -157,9 +419,12 @@ fn first_entry<T: PartialEq + Default>(slice: &[T]) -> Option<usize> {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
     use curve25519_dalek::Scalar;
+    use rand::seq::SliceRandom;
+    use rand::Rng;
 
     #[test]
     fn test_add_row() {
@@ -286,6 +551,7 @@ mod tests {
             ]),
             true
         );
+        assert!(echelon.verify_invariant());
     }
 
     #[test]
@@ -322,6 +588,290 @@ mod tests {
         assert_eq!(inverse[1][1], Scalar::from(2u32));
     }
 
+    #[test]
+    fn test_inverse_diagonal() {
+        let size = 5;
+        let mut echelon = Echelon::new(size);
+        for i in 0..size {
+            let mut row = vec![Scalar::ZERO; size];
+            row[i] = Scalar::from(i as u32 + 2);
+            assert!(echelon.add_row(row));
+        }
+        let inverse = echelon.inverse().unwrap();
+        for (i, row) in inverse.iter().enumerate() {
+            for (j, x) in row.iter().enumerate() {
+                if i == j {
+                    assert_eq!(*x, Scalar::from(i as u32 + 2).invert());
+                } else {
+                    assert_eq!(*x, Scalar::ZERO);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pivot_columns() {
+        let size = 6;
+        let mut rows: Vec<usize> = (0..size).collect();
+        rows.shuffle(&mut rand::thread_rng());
+        let mut echelon = Echelon::new(size);
+        assert!(echelon.pivot_columns().is_empty());
+        for &i in rows.iter() {
+            let mut row = vec![Scalar::ZERO; size];
+            row[i] = Scalar::ONE;
+            assert!(echelon.add_row(row));
+            let pivots = echelon.pivot_columns();
+            assert_eq!(pivots.len(), echelon.rank());
+            assert!(pivots.windows(2).all(|w| w[0] < w[1]));
+        }
+        assert_eq!(echelon.pivot_columns(), (0..size).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_would_be_independent() {
+        let mut rng = rand::thread_rng();
+        let size = 5;
+        let mut echelon = Echelon::new(size);
+        let mut rows: Vec<Vec<Scalar>> = Vec::new();
+        for _ in 0..3 * size {
+            // Combinations of the previous rows are dependent.
+            let row: Vec<Scalar> = if !rows.is_empty() && rng.gen_bool(0.4) {
+                let a = &rows[rng.gen_range(0..rows.len())];
+                let b = &rows[rng.gen_range(0..rows.len())];
+                let (x, y) = (Scalar::from(rng.gen::<u8>()), Scalar::from(3u8));
+                a.iter().zip(b).map(|(a, b)| x * a + y * b).collect()
+            } else {
+                (0..size)
+                    .map(|_| match rng.gen_range(0..2) {
+                        0 => Scalar::ZERO,
+                        _ => Scalar::from(rng.gen::<u8>()),
+                    })
+                    .collect()
+            };
+            let rank = echelon.rank();
+            let expected = echelon.would_be_independent(&row);
+            assert_eq!(echelon.rank(), rank);
+            assert_eq!(echelon.add_row(row.clone()), expected);
+            rows.push(row);
+        }
+        assert!(!echelon.would_be_independent(&vec![Scalar::ZERO; size]));
+    }
+
+    #[test]
+    fn test_rref() {
+        let mut rng = rand::thread_rng();
+        let size = 6;
+        for rank in [0, 1, 3, size] {
+            let mut echelon = Echelon::new(size);
+            while echelon.rank() < rank {
+                let row: Vec<Scalar> = (0..size)
+                    .map(|_| match rng.gen_range(0..3) {
+                        0 => Scalar::ZERO,
+                        _ => Scalar::from(rng.gen::<u64>()),
+                    })
+                    .collect();
+                echelon.add_row(row);
+            }
+            let (rref, transform) = echelon.rref();
+            assert_eq!(rref.len(), rank);
+            let coefficients = echelon.coefficients();
+            for (rref_row, transform_row) in rref.iter().zip(transform.iter()) {
+                for (k, x) in rref_row.iter().enumerate() {
+                    let product: Scalar = transform_row
+                        .iter()
+                        .zip(coefficients.iter())
+                        .map(|(t, row)| t * row[k])
+                        .sum();
+                    assert_eq!(product, *x);
+                }
+            }
+            // Every pivot is one and the only nonzero entry of its column.
+            for (i, &j) in echelon.pivot_columns().iter().enumerate() {
+                for (r, row) in rref.iter().enumerate() {
+                    let expected =
+                        if r == i { Scalar::ONE } else { Scalar::ZERO };
+                    assert_eq!(row[j], expected);
+                }
+            }
+        }
+        // For a full matrix the transform is the inverse.
+        let mut echelon = Echelon::new(3);
+        while !echelon.is_full() {
+            let row = (0..3).map(|_| Scalar::from(rng.gen::<u8>())).collect();
+            echelon.add_row(row);
+        }
+        let (rref, transform) = echelon.rref();
+        assert_eq!(rref, Echelon::new_identity(3).coefficients().clone());
+        assert_eq!(transform, echelon.inverse().unwrap());
+    }
+
+    #[test]
+    fn test_solve() {
+        let mut echelon = Echelon::new(3);
+        assert!(echelon.solve(0).is_err());
+        // Rank 2 of 3: the first chunk is reachable, the others only in
+        // combination.
+        let rows = [
+            vec![Scalar::from(2u32), Scalar::from(1u32), Scalar::from(1u32)],
+            vec![Scalar::from(1u32), Scalar::from(1u32), Scalar::from(1u32)],
+        ];
+        for row in rows.iter() {
+            assert!(echelon.add_row(row.clone()));
+        }
+        let solution = echelon.solve(0).unwrap();
+        assert_eq!(solution, vec![Scalar::ONE, -Scalar::ONE]);
+        for k in 0..3 {
+            let entry: Scalar = solution
+                .iter()
+                .zip(rows.iter())
+                .map(|(x, row)| x * row[k])
+                .sum();
+            assert_eq!(entry, if k == 0 { Scalar::ONE } else { Scalar::ZERO });
+        }
+        assert!(echelon.solve(1).is_err());
+        assert!(echelon.solve(2).is_err());
+        assert!(echelon.solve(3).is_err());
+
+        echelon.add_row(vec![Scalar::ZERO, Scalar::ZERO, Scalar::from(5u32)]);
+        for k in 0..3 {
+            assert!(echelon.solve(k).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_compound_scalars_u32() {
+        let mut echelon = Echelon::new(2);
+        echelon.add_row(vec![Scalar::from(3u32), Scalar::from(1u32)]);
+        echelon.add_row(vec![Scalar::from(1u32), Scalar::from(2u32)]);
+        assert_eq!(echelon.compound_scalars_u32(&[2, 5]).unwrap(), [11, 12]);
+        assert_eq!(
+            echelon.compound_scalars(&[2, 5]),
+            [Scalar::from(11u32), Scalar::from(12u32)]
+        );
+
+        // Each hop multiplies the coefficients by up to 2 * 255.
+        let mut row = vec![Scalar::ONE, Scalar::ONE];
+        let mut hops = 0;
+        loop {
+            let mut echelon = Echelon::new(2);
+            echelon.add_row(row.clone());
+            echelon.add_row(vec![row[0], row[1] + row[1]]);
+            match echelon.compound_scalars_u32(&[255, 255]) {
+                Ok(next) => {
+                    assert_eq!(
+                        echelon.compound_scalars(&[255, 255]),
+                        next.iter()
+                            .map(|&x| Scalar::from(x))
+                            .collect::<Vec<_>>()
+                    );
+                    row = next.iter().map(|&x| Scalar::from(x)).collect();
+                    hops += 1;
+                }
+                Err(e) => {
+                    assert!(e.contains("overflows"));
+                    break;
+                }
+            }
+        }
+        assert!((2..5).contains(&hops), "{}", hops);
+
+        let mut echelon = Echelon::new(1);
+        echelon.add_row(vec![Scalar::from(u64::MAX)]);
+        assert!(echelon.compound_scalars_u32(&[1]).is_err());
+    }
+
+    #[test]
+    fn test_is_full_empty() {
+        assert!(!Echelon::new(3).is_full());
+        assert!(!Echelon::new(0).is_full());
+        assert!(Echelon::new_identity(3).is_full());
+    }
+
+    #[test]
+    fn test_serialization() {
+        let mut rng = rand::thread_rng();
+        let mut echelon = Echelon::new(4);
+        while echelon.rank() < 3 {
+            echelon.add_row(
+                (0..4).map(|_| Scalar::from(rng.gen::<u8>())).collect(),
+            );
+        }
+        let bytes = bincode::serialize(&echelon).unwrap();
+        let mut restored: Echelon = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(restored.rank(), echelon.rank());
+        assert!(restored.verify_invariant());
+
+        while !echelon.is_full() {
+            let row: Vec<Scalar> =
+                (0..4).map(|_| Scalar::from(rng.gen::<u8>())).collect();
+            assert_eq!(echelon.add_row(row.clone()), restored.add_row(row));
+        }
+        assert_eq!(restored.inverse().unwrap(), echelon.inverse().unwrap());
+    }
+
+    #[test]
+    fn test_rank() {
+        let mut rng = rand::thread_rng();
+        let mut echelon = Echelon::new(4);
+        assert_eq!(echelon.rank(), 0);
+        let rows: Vec<Vec<Scalar>> = (0..3)
+            .map(|_| (0..4).map(|_| Scalar::from(rng.gen::<u8>())).collect())
+            .collect();
+        for row in rows.iter() {
+            let rank = echelon.rank();
+            let added = echelon.add_row(row.clone());
+            assert_eq!(echelon.rank(), rank + added as usize);
+        }
+        // Dependent rows don't change the rank.
+        let rank = echelon.rank();
+        let sum: Vec<Scalar> =
+            (0..4).map(|j| rows[0][j] + rows[1][j]).collect();
+        assert!(!echelon.add_row(sum));
+        assert!(!echelon.add_row(rows[2].clone()));
+        assert!(!echelon.add_row(vec![Scalar::ZERO; 4]));
+        assert_eq!(echelon.rank(), rank);
+        assert_eq!(Echelon::new_identity(4).rank(), 4);
+    }
+
+    #[test]
+    fn test_inverse_ct() {
+        let mut rng = rand::thread_rng();
+        let mut echelon = Echelon::new(6);
+        // Sparse rows so that the variable time path skips some work.
+        while !echelon.is_full() {
+            let row: Vec<Scalar> = (0..6)
+                .map(|_| match rng.gen_range(0..3) {
+                    0 => Scalar::from(rng.gen::<u64>()),
+                    _ => Scalar::ZERO,
+                })
+                .collect();
+            echelon.add_row(row);
+        }
+        let inverse = echelon.inverse_ct().unwrap();
+        assert_eq!(inverse, echelon.inverse_vartime().unwrap());
+        assert_eq!(inverse, echelon.inverse().unwrap());
+        assert!(Echelon::new(6).inverse_ct().is_err());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_inverse_parallel() {
+        let mut rng = rand::thread_rng();
+        let size = 16;
+        let mut echelon = Echelon::new(size);
+        while !echelon.is_full() {
+            let row: Vec<Scalar> =
+                (0..size).map(|_| Scalar::from(rng.gen::<u64>())).collect();
+            echelon.add_row(row);
+        }
+        for constant_time in [false, true] {
+            assert_eq!(
+                echelon.back_substitute(constant_time, true).unwrap(),
+                echelon.back_substitute(constant_time, false).unwrap()
+            );
+        }
+    }
+
     #[test]
     fn test_compound_scalars() {
         let echelon = Echelon::new(3);
@@ -0,0 +1,315 @@
+/*
+BareissEchelon mirrors Echelon over i128 entries, using Bareiss' fraction-free elimination to keep
+them bounded. The rows are eliminated in arrival order, each one against the previous rows with
+their pivot columns, so after eliminating k rows every entry is a (k+1)-minor of the coefficient
+matrix and the division by the previous pivot is exact. The entries are bounded by Hadamard's
+bound instead of doubling with every row, and the methods fail if they don't fit in i128.
+*/
+use curve25519_dalek::Scalar;
+
+#[derive(Clone)]
+pub struct BareissEchelon {
+    coefficients: Vec<Vec<i128>>,
+    // Eliminated rows in arrival order, with the column of their pivot.
+    echelon: Vec<Vec<i128>>,
+    pivots: Vec<usize>,
+    size: usize,
+}
+
+impl BareissEchelon {
+    pub fn new(size: usize) -> Self {
+        BareissEchelon {
+            coefficients: Vec::new(),
+            echelon: Vec::new(),
+            pivots: Vec::new(),
+            size,
+        }
+    }
+
+    pub fn new_identity(size: usize) -> Self {
+        let identity: Vec<Vec<i128>> = (0..size)
+            .map(|i| (0..size).map(|j| (i == j) as i128).collect())
+            .collect();
+        BareissEchelon {
+            coefficients: identity.clone(),
+            echelon: identity,
+            pivots: (0..size).collect(),
+            size,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.size > 0 && self.coefficients.len() == self.size
+    }
+
+    pub fn rank(&self) -> usize {
+        self.coefficients.len()
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn coefficients(&self) -> &Vec<Vec<i128>> {
+        &self.coefficients
+    }
+
+    // pivot_columns returns the column of the leading entry of each eliminated
+    // row, in increasing order like Echelon::pivot_columns.
+    pub fn pivot_columns(&self) -> Vec<usize> {
+        let mut pivots = self.pivots.clone();
+        pivots.sort_unstable();
+        pivots
+    }
+
+    // reduce eliminates the row against the previous ones.
+    fn reduce(&self, row: &[i128]) -> Result<Vec<i128>, String> {
+        let mut reduced = row.to_vec();
+        let mut previous = 1;
+        for (echelon_row, &j) in self.echelon.iter().zip(self.pivots.iter()) {
+            let pivot = echelon_row[j];
+            let f = reduced[j];
+            for (x, y) in reduced.iter_mut().zip(echelon_row.iter()) {
+                *x = bareiss_step(pivot, *x, f, *y, previous)?;
+            }
+            previous = pivot;
+        }
+        Ok(reduced)
+    }
+
+    // add_row adds the row if it is linearly independent of the previous ones
+    // and returns whether it was added. It fails if the elimination overflows
+    // i128, in which case the row is not added.
+    pub fn add_row(&mut self, row: Vec<i128>) -> Result<bool, String> {
+        if row.len() != self.size || self.is_full() {
+            return Ok(false);
+        }
+        let reduced = self.reduce(&row)?;
+        let Some(j) = reduced.iter().position(|x| *x != 0) else {
+            return Ok(false);
+        };
+        self.echelon.push(reduced);
+        self.pivots.push(j);
+        self.coefficients.push(row);
+        Ok(true)
+    }
+
+    // would_be_independent returns whether add_row would accept the row,
+    // without modifying the echelon.
+    pub fn would_be_independent(&self, row: &[i128]) -> Result<bool, String> {
+        if row.len() != self.size || self.is_full() {
+            return Ok(false);
+        }
+        Ok(self.reduce(row)?.iter().any(|x| *x != 0))
+    }
+
+    // compound_scalars returns the combination of the coefficients with the
+    // given u8 scalars, like Echelon::compound_scalars, failing on overflow.
+    pub fn compound_scalars(
+        &self,
+        scalars: &[u8],
+    ) -> Result<Vec<i128>, String> {
+        (0..self.size)
+            .map(|j| {
+                scalars.iter().zip(self.coefficients.iter()).try_fold(
+                    0i128,
+                    |sum, (x, coeffs)| {
+                        (*x as i128)
+                            .checked_mul(coeffs[j])
+                            .and_then(|y| sum.checked_add(y))
+                            .ok_or_else(overflow)
+                    },
+                )
+            })
+            .collect()
+    }
+
+    // inverse returns the inverse of the coefficient matrix over the scalars,
+    // like Echelon::inverse, dividing the adjugate by the determinant.
+    pub fn inverse(&self) -> Result<Vec<Vec<Scalar>>, String> {
+        let (adjugate, d) = self.adjugate()?;
+        let d_inverse = to_scalar(d).invert();
+        Ok(adjugate
+            .iter()
+            .map(|row| row.iter().map(|x| to_scalar(*x) * d_inverse).collect())
+            .collect())
+    }
+
+    // adjugate returns the adjugate-like matrix m and the scalar d such that
+    // coefficients * m = d * I, computed with fraction-free Gauss-Jordan
+    // elimination. d is the determinant of the coefficients up to sign.
+    pub fn adjugate(&self) -> Result<(Vec<Vec<i128>>, i128), String> {
+        if !self.is_full() {
+            return Err("The echelon form is not square".to_string());
+        }
+        let n = self.size;
+        let mut a: Vec<Vec<i128>> = self
+            .coefficients
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let mut extended = row.clone();
+                extended.extend((0..n).map(|j| (i == j) as i128));
+                extended
+            })
+            .collect();
+        let mut previous = 1;
+        for k in 0..n {
+            let p = (k..n)
+                .find(|&i| a[i][k] != 0)
+                .ok_or("The coefficients are singular")?;
+            a.swap(k, p);
+            let pivot_row = a[k].clone();
+            for (i, row) in a.iter_mut().enumerate() {
+                if i == k {
+                    continue;
+                }
+                let f = row[k];
+                for (x, y) in row.iter_mut().zip(pivot_row.iter()) {
+                    *x = bareiss_step(pivot_row[k], *x, f, *y, previous)?;
+                }
+            }
+            previous = pivot_row[k];
+        }
+        // The left half is now previous * I, the right half the inverse
+        // times previous.
+        let inverse = a.into_iter().map(|row| row[n..].to_vec()).collect();
+        Ok((inverse, previous))
+    }
+}
+
+fn overflow() -> String {
+    "Bareiss elimination overflowed i128".to_string()
+}
+
+// bareiss_step returns (pivot * x - f * y) / previous, which is exact.
+fn bareiss_step(
+    pivot: i128,
+    x: i128,
+    f: i128,
+    y: i128,
+    previous: i128,
+) -> Result<i128, String> {
+    let numerator = pivot
+        .checked_mul(x)
+        .and_then(|a| f.checked_mul(y).and_then(|b| a.checked_sub(b)))
+        .ok_or_else(overflow)?;
+    debug_assert_eq!(numerator % previous, 0);
+    Ok(numerator / previous)
+}
+
+fn to_scalar(x: i128) -> Scalar {
+    let magnitude = Scalar::from(x.unsigned_abs());
+    if x < 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::Echelon;
+    use rand::Rng;
+
+    #[test]
+    fn test_rank_matches_echelon() {
+        let mut rng = rand::thread_rng();
+        let size = 8;
+        for _ in 0..20 {
+            let mut bareiss = BareissEchelon::new(size);
+            let mut echelon = Echelon::new(size);
+            let mut rows: Vec<Vec<i128>> = Vec::new();
+            for _ in 0..2 * size {
+                // Sparse rows, and combinations of the previous rows, so that
+                // some of them are dependent.
+                let row: Vec<i128> = if !rows.is_empty() && rng.gen_bool(0.3) {
+                    let a = &rows[rng.gen_range(0..rows.len())];
+                    let b = &rows[rng.gen_range(0..rows.len())];
+                    let (x, y) = (rng.gen_range(-3..4), rng.gen_range(-3..4));
+                    a.iter().zip(b).map(|(a, b)| x * a + y * b).collect()
+                } else {
+                    (0..size)
+                        .map(|_| match rng.gen_range(0..3) {
+                            0 => 0,
+                            _ => rng.gen::<u8>() as i128,
+                        })
+                        .collect()
+                };
+                let scalars: Vec<Scalar> =
+                    row.iter().map(|x| to_scalar(*x)).collect();
+                assert_eq!(
+                    bareiss.would_be_independent(&row),
+                    Ok(echelon.would_be_independent(&scalars))
+                );
+                assert_eq!(
+                    bareiss.add_row(row.clone()),
+                    Ok(echelon.add_row(scalars))
+                );
+                assert_eq!(bareiss.rank(), echelon.rank());
+                rows.push(row);
+            }
+        }
+    }
+
+    #[test]
+    fn test_inverse() {
+        let mut rng = rand::thread_rng();
+        let size = 6;
+        let mut bareiss = BareissEchelon::new(size);
+        let mut echelon = Echelon::new(size);
+        assert!(bareiss.inverse().is_err());
+        while !bareiss.is_full() {
+            let row: Vec<i128> =
+                (0..size).map(|_| rng.gen::<u8>() as i128).collect();
+            let scalars = row.iter().map(|x| to_scalar(*x)).collect();
+            assert_eq!(bareiss.add_row(row), Ok(echelon.add_row(scalars)));
+        }
+        let (inverse, d) = bareiss.adjugate().unwrap();
+        assert_ne!(d, 0);
+        let a = bareiss.coefficients();
+        for (i, row) in a.iter().enumerate() {
+            for j in 0..size {
+                let entry: i128 =
+                    row.iter().zip(&inverse).map(|(x, m)| x * m[j]).sum();
+                assert_eq!(entry, if i == j { d } else { 0 });
+            }
+        }
+        // Same inverse as the Scalar version once divided by d.
+        assert_eq!(bareiss.inverse().unwrap(), echelon.inverse().unwrap());
+        assert_eq!(bareiss.pivot_columns(), echelon.pivot_columns());
+    }
+
+    #[test]
+    fn test_identity_and_compound() {
+        let size = 4;
+        let bareiss = BareissEchelon::new_identity(size);
+        let echelon = Echelon::new_identity(size);
+        assert!(bareiss.is_full());
+        assert_eq!(bareiss.pivot_columns(), echelon.pivot_columns());
+        let scalars = [3u8, 0, 7, 255];
+        let compound = bareiss.compound_scalars(&scalars).unwrap();
+        assert_eq!(compound, vec![3, 0, 7, 255]);
+        assert_eq!(
+            compound.iter().map(|x| to_scalar(*x)).collect::<Vec<_>>(),
+            echelon.compound_scalars(&scalars)
+        );
+        assert_eq!(bareiss.inverse().unwrap(), echelon.inverse().unwrap());
+    }
+
+    #[test]
+    fn test_overflow() {
+        // Entries near i128::MAX overflow the first elimination step instead
+        // of panicking, and the row is not added.
+        let mut bareiss = BareissEchelon::new(2);
+        assert_eq!(bareiss.add_row(vec![i128::MAX, 1]), Ok(true));
+        assert!(bareiss.add_row(vec![i128::MAX, 2]).is_err());
+        assert!(bareiss.would_be_independent(&[i128::MAX, 2]).is_err());
+        assert_eq!(bareiss.rank(), 1);
+
+        let mut big = BareissEchelon::new_identity(1);
+        big.coefficients[0][0] = i128::MAX;
+        assert!(big.compound_scalars(&[2]).is_err());
+    }
+}
@@ -1,39 +1,157 @@
 use crate::blocks::{
-    block_to_chunks, chunk_to_scalars, scalars_to_chunk, Committer,
+    block_to_chunks, chunk_to_scalars, max_chunk_len, pad_block, scalar_to_u64,
+    scalars_to_chunk, unpad_block, Committer,
 };
+use crate::hash;
 use crate::matrix::Echelon;
 use curve25519_dalek::ristretto::RistrettoPoint;
-use curve25519_dalek::traits::MultiscalarMul;
+use curve25519_dalek::traits::{MultiscalarMul, VartimeMultiscalarMul};
 use curve25519_dalek::Scalar;
 use rand::Rng;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
 
 /*
 A Message represents a single chunk that is received by the node.
 In production it will also have the BLS signature, which we are removing
 to meassure the performance of the RLNC encoding.
+Deserializing a message decompresses every commitment and fails for encodings
+that are not valid Ristretto points, so verify never runs on malformed points.
 */
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     chunk: Chunk,
     commitments: Vec<RistrettoPoint>,
+    // Number of nodes that coded the chunk, each send adds one.
+    hops: u32,
 }
+// A CommitmentHeader carries the commitments shared by all the messages of a
+// generation, so they can be sent once ahead of the coded chunks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommitmentHeader {
+    commitments: Vec<RistrettoPoint>,
+}
+
+impl CommitmentHeader {
+    pub fn new(commitments: Vec<RistrettoPoint>) -> Self {
+        CommitmentHeader { commitments }
+    }
+
+    pub fn commitments(&self) -> &Vec<RistrettoPoint> {
+        &self.commitments
+    }
+}
+
 // A Chunk contains the transmitted data. Coefficients are also in the Ristretto group
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Chunk {
     data: Vec<Scalar>,
     coefficients: Vec<Scalar>,
 }
+
+// Debug prints small coefficients as integers and only the size of the data.
+impl fmt::Debug for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let coefficients: Vec<String> = self
+            .coefficients
+            .iter()
+            .map(|c| match scalar_to_u64(c) {
+                Some(x) => x.to_string(),
+                None => format!("{:?}", c),
+            })
+            .collect();
+        f.debug_struct("Chunk")
+            .field("data", &format_args!("[{} scalars]", self.data.len()))
+            .field(
+                "coefficients",
+                &format_args!("[{}]", coefficients.join(", ")),
+            )
+            .finish()
+    }
+}
 /*
 A Node keeps chunks and the full commitments from the source. The Echelon object is used to keep
 track of the linear independence of the chunks.
+Receiving a message runs the cheap structural checks first and the multiscalar multiplication
+of the verification last.
+A node may only track a window of the source chunks, starting at column_offset, in which case the
+echelon only covers the window while messages carry the coefficients for the whole generation.
 */
 pub struct Node<'a> {
     chunks: Vec<Vec<Scalar>>,
     commitments: Vec<RistrettoPoint>,
     echelon: Echelon,
     committer: &'a Committer,
+    column_offset: usize,
+    // In trusted dedup mode dependent messages are dropped before verifying
+    // them, so an invalid dependent message is reported as dependent.
+    trusted_dedup: bool,
+    // Messages received so far, in order, once recording is enabled.
+    received: Option<Vec<Message>>,
+    // Probability of verifying each incoming message, see
+    // set_verify_sample_rate.
+    verify_sample_rate: f64,
+    // Messages sent by send_interleaved and systematic messages among them,
+    // the latter picks the next source chunk to send.
+    interleaved_sent: AtomicUsize,
+    systematic_sent: AtomicUsize,
+    // Length of the original block when it was padded, see
+    // new_source_padded.
+    block_len: Option<usize>,
+    // Highest hop count of the stored messages, the sent ones have one more.
+    hops: u32,
+    // Messages with more hops are rejected, see set_hop_limit.
+    hop_limit: Option<u32>,
+    // Check that the sent coefficients fit in a u32, see
+    // set_u32_coefficients.
+    u32_coefficients: bool,
+    // Hash of the commitments, computed on the first message checked against
+    // them. The commitments never change once set.
+    commitments_hash: OnceLock<[u8; 32]>,
+    // Inverse of the coefficient matrix, computed by the first decode and
+    // reused by the next ones. receive drops it when it adds a row.
+    inverse: OnceLock<Vec<Vec<Scalar>>>,
+}
+
+// ReplayLog is the serialized form of the messages received by a node, see
+// Node::export_replay.
+#[derive(Serialize, Deserialize)]
+struct ReplayLog {
+    num_chunks: usize,
+    column_offset: usize,
+    trusted_dedup: bool,
+    block_len: Option<usize>,
+    verify_sample_rate: f64,
+    hop_limit: Option<u32>,
+    u32_coefficients: bool,
+    messages: Vec<Message>,
+}
+
+// NodeState is the serialized form of a node, see Node::to_bytes.
+#[derive(Serialize, Deserialize)]
+struct NodeState {
+    chunks: Vec<Vec<Scalar>>,
+    commitments: Vec<RistrettoPoint>,
+    echelon: Echelon,
+    column_offset: usize,
+    trusted_dedup: bool,
+    verify_sample_rate: f64,
+    block_len: Option<usize>,
+    hops: u32,
+    hop_limit: Option<u32>,
+    u32_coefficients: bool,
+}
+
+// AuditNode verifies messages and counts them, without storing their chunks.
+pub struct AuditNode<'a> {
+    committer: &'a Committer,
+    valid: usize,
+    invalid: usize,
 }
 
 #[derive(Debug)]
@@ -42,18 +160,127 @@ pub enum ReceiveError {
     ExistingChunksMismatch(String),
     InvalidMessage(String),
     LinearlyDependentChunk,
+    HopLimitExceeded,
+}
+
+impl fmt::Display for ReceiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReceiveError::ExistingCommitmentsMismatch(e) => {
+                write!(f, "commitments mismatch: {}", e)
+            }
+            ReceiveError::ExistingChunksMismatch(e) => {
+                write!(f, "chunks mismatch: {}", e)
+            }
+            ReceiveError::InvalidMessage(e) => {
+                write!(f, "invalid message: {}", e)
+            }
+            ReceiveError::LinearlyDependentChunk => {
+                write!(
+                    f,
+                    "the chunk is linearly dependent on the received ones"
+                )
+            }
+            ReceiveError::HopLimitExceeded => {
+                write!(f, "the message went through too many hops")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReceiveError {}
+
+// DecodeError is returned by try_decode. Underdetermined means that more
+// chunks are needed and the caller can retry after receiving them.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    Underdetermined { rank: usize, num_chunks: usize },
+    InvalidData(String),
+}
+
+// CoeffStats summarizes the magnitude of the coefficients stored by a node,
+// reading every coefficient as an unsigned integer. This is meaningful while
+// the coefficients don't wrap around the field order, see compound_scalars.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoeffStats {
+    pub max_magnitude: f64,
+    pub mean_magnitude: f64,
+}
+
+// SubspaceRelation classifies the span of a node's chunks with respect to the
+// span of another node's chunks.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SubspaceRelation {
+    Equal,
+    Subset,
+    Superset,
+    Overlapping,
+    Disjoint,
 }
 
 impl Message {
     pub fn new(chunk: Chunk, commitments: Vec<RistrettoPoint>) -> Self {
-        Message { chunk, commitments }
+        Message {
+            chunk,
+            commitments,
+            hops: 0,
+        }
+    }
+
+    pub fn hops(&self) -> u32 {
+        self.hops
+    }
+
+    // set_hops sets the hop count of the message, for messages rebuilt with
+    // from_header_and_chunk from a stream that carries it.
+    pub fn set_hops(&mut self, hops: u32) {
+        self.hops = hops;
+    }
+
+    // from_header_and_chunk rebuilds a full message from the commitment header
+    // of its generation and the coded chunk. The hop count starts at zero, see
+    // set_hops.
+    pub fn from_header_and_chunk(
+        header: &CommitmentHeader,
+        data: Vec<Scalar>,
+        coefficients: Vec<Scalar>,
+    ) -> Result<Self, String> {
+        if coefficients.len() != header.commitments.len() {
+            return Err(format!(
+                "Expected {} coefficients, got {}",
+                header.commitments.len(),
+                coefficients.len()
+            ));
+        }
+        Ok(Message {
+            chunk: Chunk { data, coefficients },
+            commitments: header.commitments.clone(),
+            hops: 0,
+        })
+    }
+
+    // header returns the commitment header of the message's generation.
+    pub fn header(&self) -> CommitmentHeader {
+        CommitmentHeader::new(self.commitments.clone())
     }
 
     fn coefficients_to_scalars(&self) -> Vec<Scalar> {
         self.chunk.coefficients.to_vec()
     }
 
+    // check_lengths checks there is one coefficient per commitment, which the
+    // multiscalar multiplication of verify relies on.
+    fn check_lengths(&self) -> Result<(), String> {
+        if self.chunk.coefficients.len() != self.commitments.len() {
+            return Err(
+                "The number of coefficients and commitments differ".to_string()
+            );
+        }
+        Ok(())
+    }
+
     pub fn verify(&self, committer: &Committer) -> Result<(), String> {
+        self.check_lengths()?;
         let msm = RistrettoPoint::multiscalar_mul(
             self.coefficients_to_scalars(),
             &self.commitments,
@@ -65,18 +292,94 @@ impl Message {
         Ok(())
     }
 
+    // verify_vartime is verify with a variable time multiscalar
+    // multiplication. The coefficients and commitments are public, so there is
+    // no secret to leak through timing and the vartime version is faster.
+    pub fn verify_vartime(&self, committer: &Committer) -> Result<(), String> {
+        self.check_lengths()?;
+        let msm = RistrettoPoint::vartime_multiscalar_mul(
+            &self.chunk.coefficients,
+            &self.commitments,
+        );
+        let commitment = committer.commit_vartime(&self.chunk.data)?;
+        if msm != commitment {
+            return Err("The commitment does not match".to_string());
+        }
+        Ok(())
+    }
+
+    // verify_against_hash verifies the message for a light node that only
+    // knows the commitments hash of the generation. Messages carrying other
+    // commitments are rejected before the multiscalar multiplication.
+    pub fn verify_against_hash(
+        &self,
+        committer: &Committer,
+        expected_hash: &[u8; 32],
+    ) -> Result<(), String> {
+        if self.commitments_hash() != *expected_hash {
+            return Err("The commitments hash does not match".to_string());
+        }
+        self.verify(committer)
+    }
+
     pub fn coefficients(&self) -> &Vec<Scalar> {
         &self.chunk.coefficients
     }
 
+    // is_systematic returns the index of the source chunk the message carries
+    // if its coefficients are a unit vector.
+    pub fn is_systematic(&self) -> Option<usize> {
+        unit_index(&self.chunk.coefficients)
+    }
+
+    // is_dependent_on returns whether the coefficients of this message are a
+    // linear combination of the coefficients of the others. Messages with a
+    // different number of coefficients are ignored.
+    pub fn is_dependent_on(&self, others: &[Message]) -> bool {
+        let mut echelon = Echelon::new(self.chunk.coefficients.len());
+        for other in others {
+            if other.chunk.coefficients.len() == self.chunk.coefficients.len() {
+                echelon.add_row(other.chunk.coefficients.clone());
+            }
+        }
+        !echelon.add_row(self.chunk.coefficients.clone())
+    }
+
+    // commitments_hash identifies the generation of the message, it uses the
+    // hash function selected by the crate features.
     pub fn commitments_hash(&self) -> [u8; 32] {
-        let mut hasher = Sha256::new();
-        let serialized = bincode::serialize(&self.commitments).unwrap();
-        hasher.update(&serialized);
-        hasher.finalize().into()
+        hash::digest(&bincode::serialize(&self.commitments).unwrap())
+    }
+
+    // serialized_len returns the size of the message serialized with bincode
+    // without serializing it, see message_len.
+    pub fn serialized_len(&self) -> usize {
+        message_len(
+            self.chunk.data.len()
+                + self.chunk.coefficients.len()
+                + self.commitments.len(),
+        )
+    }
+
+    // overhead_ratio returns the serialized bytes that are not data, mostly
+    // the coefficients and the commitments, divided by the data bytes.
+    pub fn overhead_ratio(&self) -> f64 {
+        let data_bytes = 32 * self.chunk.data.len();
+        (self.serialized_len() - data_bytes) as f64 / data_bytes as f64
     }
 }
 
+// Verification is how receive_with verifies the message.
+enum Verification {
+    ConstantTime,
+    Vartime,
+    Skip,
+}
+
+// Number of times send draws the scalars before giving up if they are all
+// zero, which for a single chunk happens with probability 1/256.
+const MAX_SEND_ATTEMPTS: usize = 16;
+
 impl<'a> Node<'a> {
     pub fn new(committer: &'a Committer, num_chunks: usize) -> Self {
         Node {
@@ -84,6 +387,32 @@ impl<'a> Node<'a> {
             commitments: Vec::new(),
             echelon: Echelon::new(num_chunks),
             committer,
+            column_offset: 0,
+            trusted_dedup: false,
+            received: None,
+            verify_sample_rate: 1.0,
+            interleaved_sent: AtomicUsize::new(0),
+            systematic_sent: AtomicUsize::new(0),
+            block_len: None,
+            hops: 0,
+            hop_limit: None,
+            u32_coefficients: false,
+            commitments_hash: OnceLock::new(),
+            inverse: OnceLock::new(),
+        }
+    }
+
+    // new_windowed creates a node that decodes the num_chunks source chunks
+    // starting at column_offset out of a larger generation. Incoming messages
+    // must have zero coefficients outside of this window.
+    pub fn new_windowed(
+        committer: &'a Committer,
+        num_chunks: usize,
+        column_offset: usize,
+    ) -> Self {
+        Node {
+            column_offset,
+            ..Node::new(committer, num_chunks)
         }
     }
     pub fn new_source(
@@ -91,19 +420,64 @@ impl<'a> Node<'a> {
         block: &[u8],
         num_chunks: usize,
     ) -> Result<Self, String> {
-        let chunks: Vec<_> = block_to_chunks(block, num_chunks)?
+        let chunks = block_to_chunks(block, num_chunks)?
             .into_iter()
-            .map(|data| chunk_to_scalars(data).unwrap())
-            .collect();
-        let commitments = chunks
-            .iter()
-            .map(|chunk| committer.commit(&chunk).unwrap())
-            .collect();
+            .map(chunk_to_scalars)
+            .collect::<Result<Vec<_>, _>>()?;
+        let commitments = committer.batch_commit(&chunks)?;
         Ok(Node {
             chunks,
             commitments,
             echelon: Echelon::new_identity(num_chunks),
             committer,
+            column_offset: 0,
+            trusted_dedup: false,
+            received: None,
+            verify_sample_rate: 1.0,
+            interleaved_sent: AtomicUsize::new(0),
+            systematic_sent: AtomicUsize::new(0),
+            block_len: None,
+            hops: 0,
+            hop_limit: None,
+            u32_coefficients: false,
+            commitments_hash: OnceLock::new(),
+            inverse: OnceLock::new(),
+        })
+    }
+
+    // new_with_commitments creates a node that only accepts messages with the
+    // given commitments, for instance the commitments of concatenated
+    // generations, see concat_commitments.
+    pub fn new_with_commitments(
+        committer: &'a Committer,
+        commitments: Vec<RistrettoPoint>,
+    ) -> Result<Self, String> {
+        if commitments.is_empty() {
+            return Err("The commitments are empty".to_string());
+        }
+        let num_chunks = commitments.len();
+        Ok(Node {
+            commitments,
+            ..Node::new(committer, num_chunks)
+        })
+    }
+
+    // new_source_padded creates a source node for a block of any size. The
+    // block is framed with its length and padded, see pad_block, so the
+    // original bytes can be recovered with decode_exact.
+    pub fn new_source_padded(
+        committer: &'a Committer,
+        block: &[u8],
+        num_chunks: usize,
+    ) -> Result<Self, String> {
+        let node = Node::new_source(
+            committer,
+            &pad_block(block, num_chunks)?,
+            num_chunks,
+        )?;
+        Ok(Node {
+            block_len: Some(block.len()),
+            ..node
         })
     }
 
@@ -124,53 +498,533 @@ impl<'a> Node<'a> {
         Ok(())
     }
 
+    // check_message_commitments compares the commitments of the message with
+    // the stored ones by their hash, so the points are not compared on every
+    // message. A different set with the same hash would need a collision of
+    // the digest.
+    fn check_message_commitments(
+        &self,
+        message: &Message,
+    ) -> Result<(), String> {
+        if self.commitments.is_empty() {
+            return Ok(());
+        }
+        if self.commitments.len() != message.commitments.len() {
+            return Err("The number of commitments is different".to_string());
+        }
+        let hash = self.commitments_hash.get_or_init(|| {
+            hash::digest(&bincode::serialize(&self.commitments).unwrap())
+        });
+        if message.commitments_hash() != *hash {
+            return Err("The commitments do not match".to_string());
+        }
+        Ok(())
+    }
+
+    // window_coefficients returns the coefficients that correspond to the
+    // node's window, checking that the rest of them are zero.
+    fn window_coefficients(
+        &self,
+        coefficients: &[Scalar],
+    ) -> Result<Vec<Scalar>, String> {
+        let end = self.column_offset + self.echelon.size();
+        if coefficients.len() < end {
+            return Err("The coefficients do not cover the window".to_string());
+        }
+        if coefficients[..self.column_offset]
+            .iter()
+            .chain(coefficients[end..].iter())
+            .any(|x| *x != Scalar::ZERO)
+        {
+            return Err(
+                "The message has coefficients outside the window".to_string()
+            );
+        }
+        Ok(coefficients[self.column_offset..end].to_vec())
+    }
+
     fn check_existing_chunks(&self, chunk: &Chunk) -> Result<(), String> {
-        if !self.chunks.is_empty() {
-            if self.chunks[0].len() != chunk.data.len() {
-                return Err("The chunk size is different".to_string());
-            }
+        if !self.chunks.is_empty() && self.chunks[0].len() != chunk.data.len() {
+            return Err("The chunk size is different".to_string());
         }
         Ok(())
     }
 
     pub fn receive(&mut self, message: Message) -> Result<(), ReceiveError> {
+        self.receive_with_rng(message, &mut rand::thread_rng())
+    }
+
+    // receive_with_rng is receive drawing whether to verify the message from
+    // the given rng, see set_verify_sample_rate. A seeded one makes the
+    // sampling reproducible.
+    pub fn receive_with_rng<R: Rng>(
+        &mut self,
+        message: Message,
+        rng: &mut R,
+    ) -> Result<(), ReceiveError> {
+        let verification = if self.verify_sample_rate >= 1.0
+            || rng.gen_bool(self.verify_sample_rate)
+        {
+            Verification::ConstantTime
+        } else {
+            Verification::Skip
+        };
+        self.receive_with(message, verification)
+    }
+
+    // receive_vartime is receive verifying the message with verify_vartime.
+    pub fn receive_vartime(
+        &mut self,
+        message: Message,
+    ) -> Result<(), ReceiveError> {
+        self.receive_with(message, Verification::Vartime)
+    }
+
+    // receive_unchecked is receive without verifying the message against its
+    // commitments, so that benchmarks measure the coding alone. It is unsafe
+    // against any peer that may send invalid chunks: they are stored and
+    // corrupt the decoded block.
+    pub fn receive_unchecked(
+        &mut self,
+        message: Message,
+    ) -> Result<(), ReceiveError> {
+        self.receive_with(message, Verification::Skip)
+    }
+
+    fn receive_with(
+        &mut self,
+        message: Message,
+        verification: Verification,
+    ) -> Result<(), ReceiveError> {
+        if let Some(received) = self.received.as_mut() {
+            received.push(message.clone());
+        }
+
+        // A full node can decode already, any further message is necessarily
+        // dependent and is not stored, which bounds the memory of a sink.
+        if self.echelon.is_full() {
+            return Err(ReceiveError::LinearlyDependentChunk);
+        }
+
+        Node::check_message_shape(&message, self.committer)
+            .map_err(ReceiveError::InvalidMessage)?;
+        if self.hop_limit.is_some_and(|limit| message.hops > limit) {
+            return Err(ReceiveError::HopLimitExceeded);
+        }
+        if message
+            .chunk
+            .coefficients
+            .iter()
+            .all(|x| *x == Scalar::ZERO)
+        {
+            return Err(ReceiveError::LinearlyDependentChunk);
+        }
+
         // If we have already committments we check that they are the same
-        self.check_existing_commitments(&message.commitments)
+        self.check_message_commitments(&message)
             .map_err(ReceiveError::ExistingCommitmentsMismatch)?;
 
         self.check_existing_chunks(&message.chunk)
             .map_err(ReceiveError::ExistingChunksMismatch)?;
 
-        message
-            .verify(&self.committer)
+        let coefficients = self
+            .window_coefficients(&message.chunk.coefficients)
             .map_err(ReceiveError::InvalidMessage)?;
 
+        if self.trusted_dedup
+            && !self.echelon.would_be_independent(&coefficients)
+        {
+            return Err(ReceiveError::LinearlyDependentChunk);
+        }
+
+        match verification {
+            Verification::ConstantTime => message.verify(self.committer),
+            Verification::Vartime => message.verify_vartime(self.committer),
+            Verification::Skip => Ok(()),
+        }
+        .map_err(ReceiveError::InvalidMessage)?;
+
         // Verify linear independence
-        if !self.echelon.add_row(message.chunk.coefficients) {
+        if !self.echelon.add_row(coefficients) {
             return Err(ReceiveError::LinearlyDependentChunk);
         }
 
         self.chunks.push(message.chunk.data);
+        self.inverse.take();
+        self.hops = self.hops.max(message.hops);
+        debug_assert!(self.chunks.len() <= self.echelon.size());
         if self.commitments.is_empty() {
             self.commitments = message.commitments;
         }
         Ok(())
     }
 
+    // verify_batch checks all the messages at once: each message is weighted
+    // by a random scalar and, since commitments are linear, the weighted sum
+    // of the combined commitments must equal the commitment of the weighted
+    // sum of the data. A single invalid message makes the check fail except
+    // with negligible probability, but the error doesn't tell which one.
+    pub fn verify_batch(&self, messages: &[Message]) -> Result<(), String> {
+        let mut rng = rand::thread_rng();
+        let mut scalars = Vec::new();
+        let mut points = Vec::new();
+        let mut data = vec![Scalar::ZERO; self.committer.len()];
+        for (i, message) in messages.iter().enumerate() {
+            Node::check_message_shape(message, self.committer)
+                .map_err(|e| format!("Message {}: {}", i, e))?;
+            let weight = Scalar::from(rng.gen::<u128>());
+            scalars
+                .extend(message.chunk.coefficients.iter().map(|c| weight * c));
+            points.extend_from_slice(&message.commitments);
+            data.iter_mut()
+                .zip(message.chunk.data.iter())
+                .for_each(|(x, y)| *x += weight * y);
+        }
+        let msm = RistrettoPoint::multiscalar_mul(scalars, &points);
+        if msm != self.committer.commit(&data)? {
+            return Err("The batch commitment does not match".to_string());
+        }
+        Ok(())
+    }
+
+    // verify_batch_parallel verifies the messages across threads and returns
+    // the result for each of them. The valid ones still have to be received
+    // one at a time, in order.
+    #[cfg(feature = "parallel")]
+    pub fn verify_batch_parallel(
+        &self,
+        messages: &[Message],
+    ) -> Vec<Result<(), String>> {
+        messages
+            .par_iter()
+            .map(|message| message.verify(self.committer))
+            .collect()
+    }
+
+    // receive_batch receives the messages in order and returns the result of
+    // each one. Dependent chunks are reported as LinearlyDependentChunk like
+    // any other error, it is up to the caller to ignore them.
+    pub fn receive_batch(
+        &mut self,
+        messages: Vec<Message>,
+    ) -> Vec<Result<(), ReceiveError>> {
+        messages
+            .into_iter()
+            .map(|message| self.receive(message))
+            .collect()
+    }
+
+    // record_received starts recording every message received, valid or not,
+    // so the node state can be reproduced with from_replay.
+    pub fn record_received(&mut self) {
+        self.received.get_or_insert_with(Vec::new);
+    }
+
+    // export_replay serializes the recorded messages along with the node
+    // settings needed to replay them.
+    pub fn export_replay(&self) -> Vec<u8> {
+        bincode::serialize(&ReplayLog {
+            num_chunks: self.echelon.size(),
+            column_offset: self.column_offset,
+            trusted_dedup: self.trusted_dedup,
+            block_len: self.block_len,
+            verify_sample_rate: self.verify_sample_rate,
+            hop_limit: self.hop_limit,
+            u32_coefficients: self.u32_coefficients,
+            messages: self.received.clone().unwrap_or_default(),
+        })
+        .unwrap()
+    }
+
+    // from_replay builds a node by receiving again the messages of a log
+    // exported by export_replay. The new node keeps recording and has the
+    // settings of the exported one. Every message is verified during the
+    // replay whatever the sample rate, so an invalid message the exported node
+    // accepted without verifying it is rejected this time.
+    pub fn from_replay(
+        committer: &'a Committer,
+        bytes: &[u8],
+    ) -> Result<Self, String> {
+        let log: ReplayLog =
+            bincode::deserialize(bytes).map_err(|e| e.to_string())?;
+        let mut node =
+            Node::new_windowed(committer, log.num_chunks, log.column_offset);
+        node.set_trusted_dedup(log.trusted_dedup);
+        node.block_len = log.block_len;
+        node.hop_limit = log.hop_limit;
+        node.u32_coefficients = log.u32_coefficients;
+        node.record_received();
+        for message in log.messages {
+            // Rejected messages are replayed as well, errors are expected.
+            let _ = node.receive_with(message, Verification::ConstantTime);
+        }
+        node.set_verify_sample_rate(log.verify_sample_rate);
+        Ok(node)
+    }
+
+    // to_bytes serializes the chunks, commitments and decoding state of the
+    // node so it can be resumed later with from_bytes. The committer is not
+    // included, and neither is the recording of received messages.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(&NodeState {
+            chunks: self.chunks.clone(),
+            commitments: self.commitments.clone(),
+            echelon: self.echelon.clone(),
+            column_offset: self.column_offset,
+            trusted_dedup: self.trusted_dedup,
+            verify_sample_rate: self.verify_sample_rate,
+            block_len: self.block_len,
+            hops: self.hops,
+            hop_limit: self.hop_limit,
+            u32_coefficients: self.u32_coefficients,
+        })
+        .unwrap()
+    }
+
+    // from_bytes restores a node serialized with to_bytes, using the given
+    // committer, which must be the one the node was created with.
+    pub fn from_bytes(
+        committer: &'a Committer,
+        bytes: &[u8],
+    ) -> Result<Self, String> {
+        let state: NodeState =
+            bincode::deserialize(bytes).map_err(|e| e.to_string())?;
+        // The shape is checked before verify_invariant, which indexes the
+        // matrices without bounds checks of its own.
+        if !state.echelon.has_valid_shape()
+            || state
+                .chunks
+                .iter()
+                .any(|chunk| chunk.len() != state.chunks[0].len())
+        {
+            return Err("The decoding state is malformed".to_string());
+        }
+        if state.chunks.len() != state.echelon.rank()
+            || !state.echelon.verify_invariant()
+        {
+            return Err("The decoding state is inconsistent".to_string());
+        }
+        // The commitments of a windowed node cover the whole generation, the
+        // window must fit in it.
+        if !state.commitments.is_empty()
+            && state.commitments.len()
+                < state.column_offset + state.echelon.size()
+        {
+            return Err("The number of commitments is different".to_string());
+        }
+        if state
+            .chunks
+            .iter()
+            .any(|chunk| chunk.len() > committer.len())
+        {
+            return Err("Chunk size is too large".to_string());
+        }
+        let mut node = Node::new_windowed(
+            committer,
+            state.echelon.size(),
+            state.column_offset,
+        );
+        node.chunks = state.chunks;
+        node.commitments = state.commitments;
+        node.echelon = state.echelon;
+        node.trusted_dedup = state.trusted_dedup;
+        node.set_verify_sample_rate(state.verify_sample_rate);
+        node.block_len = state.block_len;
+        node.hops = state.hops;
+        node.hop_limit = state.hop_limit;
+        node.u32_coefficients = state.u32_coefficients;
+        Ok(node)
+    }
+
+    // set_verify_sample_rate makes receive verify each message only with the
+    // given probability, clamped to [0, 1]. This is not safe against a
+    // malicious peer: a single unverified invalid chunk that is accepted
+    // corrupts the decoded block, and nothing detects it before the block is
+    // checked by other means. The node would also forward combinations of the
+    // invalid chunk, which honest peers then reject as invalid.
+    pub fn set_verify_sample_rate(&mut self, rate: f64) {
+        self.verify_sample_rate = rate.clamp(0.0, 1.0);
+    }
+
+    // set_hop_limit makes receive reject messages that went through more than
+    // limit nodes. Every hop multiplies the coefficients by u8 scalars, so
+    // this bounds their magnitude, see Echelon::compound_scalars.
+    pub fn set_hop_limit(&mut self, limit: Option<u32>) {
+        self.hop_limit = limit;
+    }
+
+    // set_u32_coefficients makes send and send_with_coeffs fail when the
+    // coefficients of the message would not fit in a u32, which detects too
+    // many hops deterministically. The message is still built with Scalar
+    // coefficients.
+    pub fn set_u32_coefficients(&mut self, u32_coefficients: bool) {
+        self.u32_coefficients = u32_coefficients;
+    }
+
+    // set_trusted_dedup enables dropping dependent messages before verifying
+    // them. This saves the verification of messages that would be discarded
+    // anyway, at the cost of not reporting whether they were valid.
+    pub fn set_trusted_dedup(&mut self, trusted_dedup: bool) {
+        self.trusted_dedup = trusted_dedup;
+    }
+
+    // check_message_shape checks the lengths in the message against each
+    // other and the committer. Scalars and points are canonical already, they
+    // are checked when deserialized.
+    fn check_message_shape(
+        message: &Message,
+        committer: &Committer,
+    ) -> Result<(), String> {
+        message.check_lengths()?;
+        if message.chunk.data.len() > committer.len() {
+            return Err("Chunk size is too large".to_string());
+        }
+        Ok(())
+    }
+
     pub fn send(&self) -> Result<Message, String> {
+        self.send_with_rng(&mut rand::thread_rng())
+    }
+
+    // send_with_rng is send drawing the coefficients from the given rng, a
+    // seeded one makes the messages reproducible.
+    pub fn send_with_rng<R: Rng>(
+        &self,
+        rng: &mut R,
+    ) -> Result<Message, String> {
         if self.chunks.is_empty() {
             return Err("There are no chunks to send".to_string());
         }
-        let scalars = generate_random_coeffs(self.chunks.len());
-        let chunk = self.linear_comb_chunk(&scalars);
-
-        let message = Message::new(chunk, self.commitments.clone());
-        debug_assert!(message.verify(&self.committer).is_ok());
+        // All-zero scalars give a zero chunk that no receiver accepts, draw
+        // them again.
+        let scalars = (0..MAX_SEND_ATTEMPTS)
+            .map(|_| generate_random_coeffs(self.chunks.len(), rng))
+            .find(|scalars| scalars.iter().any(|x| *x != 0))
+            .ok_or("Only drew zero coefficients")?;
+        self.check_u32_coefficients(&scalars)?;
+        let message = self.new_message(self.linear_comb_chunk(&scalars));
+        debug_assert!(message.verify(self.committer).is_ok());
         Ok(message)
     }
 
+    // recode sends a random combination of the chunks the node holds, at any
+    // rank. The coefficients are the same combination of the stored
+    // coefficients, so they stay relative to the source chunks and the message
+    // verifies against the original commitments however many relays it went
+    // through. A relay can forward before it could decode.
+    pub fn recode(&self) -> Result<Message, String> {
+        self.send()
+    }
+
+    // send_interleaved sends ratio.0 systematic messages, plain source chunks,
+    // for every ratio.1 coded ones. The systematic messages go through the
+    // source chunks the node holds in order and start over once all were sent.
+    // Nodes without source chunks always send coded messages.
+    pub fn send_interleaved(
+        &self,
+        ratio: (usize, usize),
+    ) -> Result<Message, String> {
+        let (systematic, coded) = ratio;
+        if systematic + coded == 0 {
+            return Err("The interleaving ratio is empty".to_string());
+        }
+        let sent = self.interleaved_sent.fetch_add(1, Ordering::Relaxed);
+        let rows = self.systematic_rows();
+        if sent % (systematic + coded) >= systematic || rows.is_empty() {
+            return self.send();
+        }
+        let next = self.systematic_sent.fetch_add(1, Ordering::Relaxed);
+        let mut scalars = vec![0u8; self.chunks.len()];
+        scalars[rows[next % rows.len()]] = 1;
+        Ok(self.new_message(self.linear_comb_chunk(&scalars)))
+    }
+
+    // send_systematic sends the index-th source chunk uncoded, with a unit
+    // coefficient vector. The node must hold that source chunk, as source
+    // nodes hold all of them.
+    pub fn send_systematic(&self, index: usize) -> Result<Message, String> {
+        let row = self
+            .echelon
+            .coefficients()
+            .iter()
+            .position(|row| unit_index(row) == Some(index))
+            .ok_or(format!("Source chunk {} is not available", index))?;
+        let mut scalars = vec![0u8; self.chunks.len()];
+        scalars[row] = 1;
+        self.send_with_coeffs(&scalars)
+    }
+
+    // send_with_coeffs sends the combination of the stored chunks with the
+    // given scalars, one per stored chunk, instead of random ones.
+    pub fn send_with_coeffs(&self, scalars: &[u8]) -> Result<Message, String> {
+        if self.chunks.is_empty() {
+            return Err("There are no chunks to send".to_string());
+        }
+        if scalars.len() != self.chunks.len() {
+            return Err(format!(
+                "Expected {} scalars, got {}",
+                self.chunks.len(),
+                scalars.len()
+            ));
+        }
+        self.check_u32_coefficients(scalars)?;
+        Ok(self.new_message(self.linear_comb_chunk(scalars)))
+    }
+
+    fn check_u32_coefficients(&self, scalars: &[u8]) -> Result<(), String> {
+        if self.u32_coefficients {
+            self.echelon.compound_scalars_u32(scalars)?;
+        }
+        Ok(())
+    }
+
+    // new_message wraps a chunk coded by this node, one hop further than the
+    // chunks it holds.
+    fn new_message(&self, chunk: Chunk) -> Message {
+        Message {
+            chunk,
+            commitments: self.commitments.clone(),
+            hops: self.hops + 1,
+        }
+    }
+
+    // systematic_rows returns the stored chunks that are source chunks, that
+    // is, whose coefficients are a unit vector.
+    fn systematic_rows(&self) -> Vec<usize> {
+        self.echelon
+            .coefficients()
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| unit_index(row).is_some())
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    // minimal_forward_set returns one freshly recoded message per stored chunk,
+    // together they span the node's subspace. The k-th message combines the
+    // chunks k.. with a nonzero k-th scalar, so the combination matrix is
+    // triangular and the messages are independent.
+    pub fn minimal_forward_set(&self) -> Vec<Message> {
+        let mut rng = rand::thread_rng();
+        (0..self.chunks.len())
+            .map(|k| {
+                let mut scalars =
+                    generate_random_coeffs(self.chunks.len(), &mut rng);
+                scalars[..k].fill(0);
+                scalars[k] = rng.gen_range(1..=u8::MAX);
+                self.new_message(self.linear_comb_chunk(&scalars))
+            })
+            .collect()
+    }
+
     fn linear_comb_chunk(&self, scalars: &[u8]) -> Chunk {
-        let coefficients = self.echelon.compound_scalars(scalars);
+        // Place the window coefficients back in the whole generation.
+        let mut coefficients = vec![Scalar::ZERO; self.column_offset];
+        coefficients.extend(self.echelon.compound_scalars(scalars));
+        coefficients.resize(
+            self.commitments.len().max(coefficients.len()),
+            Scalar::ZERO,
+        );
         let data = self.linear_comb_data(scalars);
         Chunk { data, coefficients }
     }
@@ -188,31 +1042,201 @@ impl<'a> Node<'a> {
     }
 
     pub fn decode(&self) -> Result<Vec<u8>, String> {
-        let inverse = self.echelon.inverse()?;
-        let mut ret: Vec<u8> = Vec::with_capacity(
-            self.commitments.len() * self.chunks[0].len() * 32,
-        );
+        let mut ret = Vec::new();
+        self.decode_to(&mut ret).map_err(|e| e.to_string())?;
+        Ok(ret)
+    }
 
+    // decode_to writes the decoded block to out one source chunk at a time,
+    // without holding the whole block in memory.
+    pub fn decode_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        let inverse = self.inverse().map_err(invalid)?;
         for i in 0..inverse.len() {
-            let mut ret_scalars = Vec::with_capacity(
-                self.commitments.len() * self.chunks[0].len(),
-            );
-            for k in 0..self.chunks[0].len() {
-                ret_scalars.push(
-                    (0..inverse.len())
-                        .map(|j| inverse[i][j] * self.chunks[j][k])
-                        .sum::<Scalar>(),
-                );
-            }
+            out.write_all(&self.decode_row(inverse, i).map_err(invalid)?)?;
+        }
+        Ok(())
+    }
 
-            ret.extend_from_slice(&scalars_to_chunk(&ret_scalars)?);
+    // try_decode decodes the block if the node has enough chunks, otherwise it
+    // returns DecodeError::Underdetermined with the current rank so the caller
+    // can keep receiving and try again.
+    pub fn try_decode(&self) -> Result<Vec<u8>, DecodeError> {
+        if !self.echelon.is_full() {
+            return Err(DecodeError::Underdetermined {
+                rank: self.echelon.rank(),
+                num_chunks: self.echelon.size(),
+            });
         }
+        self.decode().map_err(DecodeError::InvalidData)
+    }
 
-        Ok(ret)
+    // decode_exact decodes a block that was encoded with new_source_padded and
+    // returns exactly the original bytes. The length is read from the padded
+    // block, and checked against the known one if any, see set_block_len.
+    pub fn decode_exact(&self) -> Result<Vec<u8>, String> {
+        let block = unpad_block(&self.decode()?)?;
+        match self.block_len {
+            Some(len) if len != block.len() => {
+                Err(format!("Decoded {} bytes, expected {}", block.len(), len))
+            }
+            _ => Ok(block),
+        }
     }
 
-    pub fn chunks(&self) -> &Vec<Vec<Scalar>> {
-        &self.chunks
+    // block_len returns the length of the original block, if known.
+    pub fn block_len(&self) -> Option<usize> {
+        self.block_len
+    }
+
+    // set_block_len records the length of the original block when it is known
+    // out of band, decode_exact then checks it.
+    pub fn set_block_len(&mut self, len: usize) {
+        self.block_len = Some(len);
+    }
+
+    // decode_chunks decodes the source chunks tracked by the node, each paired
+    // with its index in the whole generation.
+    pub fn decode_chunks(&self) -> Result<Vec<(usize, Vec<u8>)>, String> {
+        let inverse = self.inverse()?;
+        let mut ret = Vec::with_capacity(inverse.len());
+
+        for i in 0..inverse.len() {
+            ret.push((self.column_offset + i, self.decode_row(inverse, i)?));
+        }
+
+        Ok(ret)
+    }
+
+    // decode_partial decodes the source chunks that are already recoverable,
+    // even if the node is not full, each paired with its index in the whole
+    // generation. A chunk is recoverable once its unit vector is in the span of
+    // the received coefficients. Chunks whose data does not unpack, which
+    // only happens with tampered data, are left out.
+    pub fn decode_partial(&self) -> Vec<(usize, Vec<u8>)> {
+        // Once full every chunk is recoverable and the transform is the
+        // inverse, which may be cached already.
+        if let Ok(inverse) = self.inverse() {
+            return (0..inverse.len())
+                .filter_map(|i| {
+                    let chunk = self.decode_row(inverse, i).ok()?;
+                    Some((self.column_offset + i, chunk))
+                })
+                .collect();
+        }
+        let (rref, transform) = self.echelon.rref();
+        self.echelon
+            .pivot_columns()
+            .into_iter()
+            .enumerate()
+            .filter(|&(i, j)| unit_index(&rref[i]) == Some(j))
+            .filter_map(|(i, j)| {
+                let chunk = self.decode_row(&transform, i).ok()?;
+                Some((self.column_offset + j, chunk))
+            })
+            .collect()
+    }
+
+    // inverse returns the inverse of the coefficient matrix, computing it
+    // only if it is not cached yet.
+    fn inverse(&self) -> Result<&Vec<Vec<Scalar>>, String> {
+        if let Some(inverse) = self.inverse.get() {
+            return Ok(inverse);
+        }
+        let inverse = self.echelon.inverse()?;
+        Ok(self.inverse.get_or_init(|| inverse))
+    }
+
+    // has_cached_inverse returns whether the next decode reuses the inverse
+    // computed by a previous one.
+    pub fn has_cached_inverse(&self) -> bool {
+        self.inverse.get().is_some()
+    }
+
+    // decode_row returns the i-th source chunk given the inverse of the
+    // coefficient matrix. It multiplies every entry without branching on the
+    // data, so its running time only depends on the sizes.
+    fn decode_row(
+        &self,
+        inverse: &[Vec<Scalar>],
+        i: usize,
+    ) -> Result<Vec<u8>, String> {
+        let mut ret_scalars = Vec::with_capacity(self.chunks[0].len());
+        for k in 0..self.chunks[0].len() {
+            ret_scalars.push(
+                (0..inverse.len())
+                    .map(|j| inverse[i][j] * self.chunks[j][k])
+                    .sum::<Scalar>(),
+            );
+        }
+        Ok(scalars_to_chunk(&ret_scalars)?)
+    }
+
+    // decode_blocked decodes the block like decode and writes it to the writer,
+    // computing block_width columns of each chunk at a time to bound the
+    // memory in use. The width is rounded up to a multiple of 64 scalars, the
+    // groups in which chunk_to_scalars packs the data.
+    pub fn decode_blocked<W: Write>(
+        &self,
+        block_width: usize,
+        writer: &mut W,
+    ) -> Result<(), String> {
+        let inverse = self.inverse()?;
+        let width = self.chunks[0].len();
+        let block_width = block_width.max(1).next_multiple_of(64);
+        for row in inverse.iter() {
+            for start in (0..width).step_by(block_width) {
+                let scalars: Vec<Scalar> = (start
+                    ..(start + block_width).min(width))
+                    .map(|k| {
+                        row.iter()
+                            .zip(self.chunks.iter())
+                            .map(|(x, chunk)| x * chunk[k])
+                            .sum()
+                    })
+                    .collect();
+                writer
+                    .write_all(&scalars_to_chunk(&scalars)?)
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(())
+    }
+
+    // decode_range returns the bytes [start, end) of the decoded block,
+    // decoding only the chunks that overlap the range.
+    pub fn decode_range(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> Result<Vec<u8>, String> {
+        let inverse = self.inverse()?;
+        let chunk_len = max_chunk_len(self.chunks[0].len());
+        if start > end || end > chunk_len * inverse.len() {
+            return Err(format!(
+                "Invalid range {}..{} for a block of {} bytes",
+                start,
+                end,
+                chunk_len * inverse.len()
+            ));
+        }
+        if start == end {
+            return Ok(Vec::new());
+        }
+        let first = start / chunk_len;
+        let last = (end - 1) / chunk_len;
+        let mut ret = Vec::with_capacity(end - start);
+        for i in first..=last {
+            let chunk = self.decode_row(inverse, i)?;
+            let from = start.saturating_sub(i * chunk_len);
+            let to = (end - i * chunk_len).min(chunk_len);
+            ret.extend_from_slice(&chunk[from..to]);
+        }
+        Ok(ret)
+    }
+
+    pub fn chunks(&self) -> &Vec<Vec<Scalar>> {
+        &self.chunks
     }
 
     pub fn commitments(&self) -> &Vec<RistrettoPoint> {
@@ -222,25 +1246,581 @@ impl<'a> Node<'a> {
     pub fn is_full(&self) -> bool {
         self.echelon.is_full()
     }
+
+    // rank returns the number of independent chunks received.
+    pub fn rank(&self) -> usize {
+        self.echelon.rank()
+    }
+
+    // num_chunks returns the number of source chunks the node decodes.
+    pub fn num_chunks(&self) -> usize {
+        self.echelon.size()
+    }
+
+    // chunk_size_scalars returns the number of scalars of each chunk, once
+    // the node holds any.
+    pub fn chunk_size_scalars(&self) -> Option<usize> {
+        self.chunks.first().map(|chunk| chunk.len())
+    }
+
+    // progress returns the number of independent chunks received and the
+    // number of chunks needed to decode.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.echelon.rank(), self.echelon.size())
+    }
+
+    // fraction_complete returns the progress as a fraction in [0, 1].
+    pub fn fraction_complete(&self) -> f64 {
+        let (rank, num_chunks) = self.progress();
+        if num_chunks == 0 {
+            return 0.0;
+        }
+        rank as f64 / num_chunks as f64
+    }
+
+    // coefficient_stats returns the largest and the mean magnitude over the
+    // coefficient rows received so far.
+    pub fn coefficient_stats(&self) -> CoeffStats {
+        let magnitudes: Vec<f64> = self
+            .echelon
+            .coefficients()
+            .iter()
+            .flatten()
+            .map(scalar_magnitude)
+            .collect();
+        if magnitudes.is_empty() {
+            return CoeffStats {
+                max_magnitude: 0.0,
+                mean_magnitude: 0.0,
+            };
+        }
+        CoeffStats {
+            max_magnitude: magnitudes.iter().cloned().fold(0.0, f64::max),
+            mean_magnitude: magnitudes.iter().sum::<f64>()
+                / magnitudes.len() as f64,
+        }
+    }
+
+    // eta_rounds estimates the rounds left until the node is full if it keeps
+    // receiving innovative_per_round independent chunks per round.
+    pub fn eta_rounds(&self, innovative_per_round: f64) -> f64 {
+        let missing = self.echelon.size() - self.echelon.rank();
+        if missing == 0 {
+            return 0.0;
+        }
+        if innovative_per_round <= 0.0 {
+            return f64::INFINITY;
+        }
+        missing as f64 / innovative_per_round
+    }
+
+    pub fn column_offset(&self) -> usize {
+        self.column_offset
+    }
+
+    // subspace_relation compares the subspace spanned by this node's chunks with
+    // the one spanned by the other node's. Both nodes need to be on the same
+    // generation, that is have the same commitments if any, and track the
+    // same window of it.
+    pub fn subspace_relation(
+        &self,
+        other: &Node,
+    ) -> Result<SubspaceRelation, String> {
+        if self.echelon.size() != other.echelon.size() {
+            return Err("The number of chunks is different".to_string());
+        }
+        if self.column_offset != other.column_offset {
+            return Err("The column offsets are different".to_string());
+        }
+        if !self.commitments.is_empty() && !other.commitments.is_empty() {
+            self.check_existing_commitments(&other.commitments)?;
+        }
+        let rank = self.echelon.rank();
+        let other_rank = other.echelon.rank();
+        let mut union = self.echelon.clone();
+        let added = other
+            .echelon
+            .coefficients()
+            .iter()
+            .filter(|row| union.add_row(row.to_vec()))
+            .count();
+        // dim(A ∩ B) = dim(B) - (dim(A + B) - dim(A))
+        let intersection = other_rank - added;
+        Ok(match (intersection == rank, intersection == other_rank) {
+            (true, true) => SubspaceRelation::Equal,
+            (true, false) => SubspaceRelation::Subset,
+            (false, true) => SubspaceRelation::Superset,
+            (false, false) if intersection == 0 => SubspaceRelation::Disjoint,
+            (false, false) => SubspaceRelation::Overlapping,
+        })
+    }
+}
+
+// message_len returns the size of a serialized message with the given total
+// number of data scalars, coefficients and commitments: each of the three
+// vectors has an 8-byte length followed by its 32-byte scalars or compressed
+// points, then come the 4-byte hops.
+fn message_len(items: usize) -> usize {
+    3 * 8 + 32 * items + 4
+}
+
+// suggest_num_chunks returns the smallest number of chunks for which a message
+// of a block of block_len bytes serializes to at most target_msg_bytes, or 0
+// if there is none. The block is taken as padded to a multiple of 32 bytes per
+// chunk. A message with n chunks of s scalars carries s scalars of data, n
+// coefficients and n commitments, see message_len. Chunks that need more
+// scalars than committer_len can't be committed to.
+pub fn suggest_num_chunks(
+    block_len: usize,
+    committer_len: usize,
+    target_msg_bytes: usize,
+) -> usize {
+    let max_chunks = block_len.div_ceil(32).max(1);
+    (1..=max_chunks)
+        .find(|&n| {
+            let words = block_len.div_ceil(n * 32);
+            let scalars = words + words.div_ceil(63);
+            scalars <= committer_len
+                && message_len(scalars + 2 * n) <= target_msg_bytes
+        })
+        .unwrap_or(0)
+}
+
+// unit_index returns the position of the one if the vector is a unit vector.
+fn unit_index(row: &[Scalar]) -> Option<usize> {
+    let mut nonzero =
+        row.iter().enumerate().filter(|(_, x)| **x != Scalar::ZERO);
+    match (nonzero.next(), nonzero.next()) {
+        (Some((i, x)), None) if *x == Scalar::ONE => Some(i),
+        _ => None,
+    }
 }
 
-fn generate_random_coeffs(length: usize) -> Vec<u8> {
-    let mut rng = rand::thread_rng();
+fn generate_random_coeffs<R: Rng>(length: usize, rng: &mut R) -> Vec<u8> {
     (0..length).map(|_| rng.gen()).collect()
 }
 
+impl<'a> AuditNode<'a> {
+    pub fn new(committer: &'a Committer) -> Self {
+        AuditNode {
+            committer,
+            valid: 0,
+            invalid: 0,
+        }
+    }
+
+    // audit verifies the message and returns whether it is valid.
+    pub fn audit(&mut self, message: &Message) -> bool {
+        let ok = self.committer.audit_message(message).is_ok();
+        if ok {
+            self.valid += 1;
+        } else {
+            self.invalid += 1;
+        }
+        ok
+    }
+
+    pub fn valid(&self) -> usize {
+        self.valid
+    }
+
+    pub fn invalid(&self) -> usize {
+        self.invalid
+    }
+}
+
+// scalar_magnitude returns the canonical representative of the scalar as a
+// float, rounding it for values over 2^53.
+fn scalar_magnitude(scalar: &Scalar) -> f64 {
+    scalar
+        .as_bytes()
+        .iter()
+        .rev()
+        .fold(0.0, |acc, &b| acc * 256.0 + b as f64)
+}
+
 #[cfg(test)]
 mod tests {
     use rand::RngCore;
 
-    use crate::blocks::{random_u8_slice, Committer};
-    use crate::node::{Node, ReceiveError};
+    use crate::blocks::{
+        concat_commitments, random_u8_slice, scalars_to_chunk, Committer,
+    };
+    use crate::node::{
+        suggest_num_chunks, AuditNode, Chunk, DecodeError, Message, Node,
+        ReceiveError, SubspaceRelation,
+    };
+    use curve25519_dalek::Scalar;
+
+    // receive_checked receives the message and checks that the echelon is still
+    // consistent and that its rank matches the number of accepted messages.
+    fn receive_checked(
+        node: &mut Node,
+        message: Message,
+        accepted: &mut usize,
+    ) -> Result<(), ReceiveError> {
+        let res = node.receive(message);
+        if res.is_ok() {
+            *accepted += 1;
+        }
+        assert!(node.echelon.verify_invariant());
+        assert_eq!(node.rank(), *accepted);
+        res
+    }
+
+    // message_with_scalars builds a message out of the given combination of
+    // the node's chunks.
+    fn message_with_scalars(node: &Node, scalars: &[u8]) -> Message {
+        Message::new(
+            node.linear_comb_chunk(scalars),
+            node.commitments().clone(),
+        )
+    }
+
+    #[test]
+    fn test_send_with_rng() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let num_chunks = 4;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        let send = |seed| {
+            let message = source_node
+                .send_with_rng(&mut StdRng::seed_from_u64(seed))
+                .unwrap();
+            bincode::serialize(&message).unwrap()
+        };
+        assert_eq!(send(1), send(1));
+        assert_ne!(send(1), send(2));
+        assert!(Node::new(&committer, num_chunks)
+            .send_with_rng(&mut StdRng::seed_from_u64(1))
+            .is_err());
+    }
+
+    #[test]
+    fn test_send_zero_scalars() {
+        use rand::rngs::mock::StepRng;
+
+        let committer = Committer::new(2);
+        let source_node =
+            Node::new_source(&committer, &random_u8_slice(32), 1).unwrap();
+        // The first draw is zero, the second one is one.
+        let message =
+            source_node.send_with_rng(&mut StepRng::new(0, 1)).unwrap();
+        assert_eq!(message.coefficients(), &vec![Scalar::ONE]);
+        let mut destination_node = Node::new(&committer, 1);
+        destination_node.receive(message).unwrap();
+        assert!(destination_node.is_full());
+
+        assert!(source_node.send_with_rng(&mut StepRng::new(0, 0)).is_err());
+    }
+
+    #[test]
+    fn test_send_systematic() {
+        let num_chunks = 3;
+        let chunk_size: usize = 2;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
+        let block = random_u8_slice(num_chunks * chunk_size * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+        let mut destination_node = Node::new(&committer, num_chunks);
+        for i in [2, 0] {
+            let message = source_node.send_systematic(i).unwrap();
+            assert_eq!(message.is_systematic(), Some(i));
+            assert!(message.verify(&committer).is_ok());
+            assert_eq!(
+                scalars_to_chunk(&message.chunk.data).unwrap(),
+                block[i * chunk_size * 32..(i + 1) * chunk_size * 32]
+            );
+            destination_node.receive(message).unwrap();
+        }
+        assert!(source_node.send_systematic(3).is_err());
+
+        // Only the source chunks received uncoded can be sent again.
+        assert!(destination_node.send_systematic(0).is_ok());
+        assert!(destination_node.send_systematic(1).is_err());
+    }
+
+    #[test]
+    fn test_send_with_coeffs() {
+        let num_chunks = 3;
+        let committer = Committer::new(2);
+        let block = random_u8_slice(num_chunks * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+        let message = source_node.send_with_coeffs(&[1, 0, 0]).unwrap();
+        assert_eq!(
+            message.coefficients(),
+            &vec![Scalar::ONE, Scalar::ZERO, Scalar::ZERO]
+        );
+        assert_eq!(scalars_to_chunk(&message.chunk.data).unwrap(), block[..32]);
+
+        let message = source_node.send_with_coeffs(&[2, 0, 3]).unwrap();
+        assert!(message.verify(&committer).is_ok());
+        assert_eq!(
+            message.coefficients(),
+            &vec![Scalar::from(2u8), Scalar::ZERO, Scalar::from(3u8)]
+        );
+        assert!(source_node.send_with_coeffs(&[1, 0]).is_err());
+        assert!(Node::new(&committer, num_chunks)
+            .send_with_coeffs(&[])
+            .is_err());
+    }
+
+    #[test]
+    fn test_node_to_bytes() {
+        let num_chunks = 3;
+        let chunk_size: usize = 2;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
+        let block = random_u8_slice(num_chunks * chunk_size * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+        let mut destination_node = Node::new(&committer, num_chunks);
+        for row in [[1, 2, 3], [0, 1, 1]] {
+            destination_node
+                .receive(message_with_scalars(&source_node, &row))
+                .unwrap();
+        }
+
+        let bytes = destination_node.to_bytes();
+        let mut restored = Node::from_bytes(&committer, &bytes).unwrap();
+        assert_eq!(restored.rank(), 2);
+        assert_eq!(restored.chunks(), destination_node.chunks());
+        assert_eq!(restored.to_bytes(), bytes);
+        assert!(matches!(
+            restored.receive(message_with_scalars(&source_node, &[1, 3, 4])),
+            Err(ReceiveError::LinearlyDependentChunk)
+        ));
+        restored
+            .receive(message_with_scalars(&source_node, &[0, 0, 1]))
+            .unwrap();
+        assert_eq!(restored.decode().unwrap(), block);
+
+        // The source node restores as a source node.
+        let restored =
+            Node::from_bytes(&committer, &source_node.to_bytes()).unwrap();
+        assert!(restored.is_full());
+        assert!(
+            Node::from_bytes(&committer, &bytes[..bytes.len() - 1]).is_err()
+        );
+        assert!(Node::from_bytes(&Committer::new(1), &bytes).is_err());
+
+        // A windowed node keeps the commitments of the whole generation.
+        let block = random_u8_slice(5 * chunk_size * 32);
+        let source_node = Node::new_source(&committer, &block, 5).unwrap();
+        let mut windowed = Node::new_windowed(&committer, 3, 1);
+        windowed
+            .receive(message_with_scalars(&source_node, &[0, 1, 2, 3, 0]))
+            .unwrap();
+        let bytes = windowed.to_bytes();
+        let mut restored = Node::from_bytes(&committer, &bytes).unwrap();
+        assert_eq!(restored.to_bytes(), bytes);
+        for row in [[0, 0, 1, 1, 0], [0, 0, 0, 1, 0]] {
+            restored
+                .receive(message_with_scalars(&source_node, &row))
+                .unwrap();
+        }
+        let chunk_len = chunk_size * 32;
+        assert_eq!(
+            restored.decode().unwrap(),
+            &block[chunk_len..4 * chunk_len]
+        );
+    }
+
+    #[test]
+    fn test_node_from_bytes_ragged() {
+        use super::NodeState;
+
+        let committer = Committer::new(2);
+        let row = |len: usize| vec![Scalar::ONE; len];
+        // Echelon serializes as its coefficients, echelon and transform.
+        let ragged = |coefficients: Vec<Vec<Scalar>>,
+                      echelon: Vec<Vec<Scalar>>,
+                      transform: Vec<Vec<Scalar>>| {
+            let matrices = (coefficients, echelon, transform);
+            NodeState {
+                chunks: vec![row(2)],
+                commitments: Vec::new(),
+                echelon: bincode::deserialize(
+                    &bincode::serialize(&matrices).unwrap(),
+                )
+                .unwrap(),
+                column_offset: 0,
+                trusted_dedup: false,
+                verify_sample_rate: 1.0,
+                block_len: None,
+                hops: 0,
+                hop_limit: None,
+                u32_coefficients: false,
+            }
+        };
+        for state in [
+            // Short transform.
+            ragged(vec![row(3)], vec![row(3)], vec![row(3)]),
+            // Ragged transform.
+            ragged(vec![row(2)], vec![row(2)], vec![row(2), row(1)]),
+            // Short coefficient and echelon rows.
+            ragged(vec![row(1)], vec![row(2)], vec![row(2), row(2)]),
+            ragged(vec![row(2)], vec![row(1)], vec![row(2), row(2)]),
+        ] {
+            let bytes = bincode::serialize(&state).unwrap();
+            assert_eq!(
+                Node::from_bytes(&committer, &bytes).err(),
+                Some("The decoding state is malformed".to_string())
+            );
+        }
+
+        // Chunks of different lengths.
+        let mut state = ragged(vec![row(2)], vec![row(2)], vec![row(2); 2]);
+        state.chunks.push(row(1));
+        let bytes = bincode::serialize(&state).unwrap();
+        assert_eq!(
+            Node::from_bytes(&committer, &bytes).err(),
+            Some("The decoding state is malformed".to_string())
+        );
+    }
+
+    #[test]
+    fn test_receive_batch() {
+        let num_chunks = 3;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        let first = message_with_scalars(&source_node, &[1, 2, 3]);
+        let second = message_with_scalars(&source_node, &[0, 1, 0]);
+        let mut tampered = message_with_scalars(&source_node, &[0, 0, 1]);
+        tampered.chunk.data[0] += Scalar::ONE;
+        let messages = vec![
+            first.clone(),
+            first.clone(),
+            second.clone(),
+            tampered,
+            message_with_scalars(&source_node, &[1, 4, 3]),
+            message_with_scalars(&source_node, &[0, 0, 1]),
+            second,
+        ];
+        let mut destination_node = Node::new(&committer, num_chunks);
+        let results = destination_node.receive_batch(messages);
+        let pattern: Vec<&str> = results
+            .iter()
+            .map(|r| match r {
+                Ok(()) => "ok",
+                Err(ReceiveError::LinearlyDependentChunk) => "dependent",
+                Err(_) => "error",
+            })
+            .collect();
+        assert_eq!(
+            pattern,
+            [
+                "ok",
+                "dependent",
+                "ok",
+                "error",
+                "dependent",
+                "ok",
+                "dependent"
+            ]
+        );
+        assert!(destination_node.is_full());
+        assert!(destination_node.receive_batch(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_num_chunks() {
+        let num_chunks = 5;
+        let chunk_size: usize = 2;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * chunk_size * 32),
+            num_chunks,
+        )
+        .unwrap();
+        assert_eq!(source_node.num_chunks(), num_chunks);
+        assert_eq!(source_node.chunk_size_scalars(), Some(3));
+
+        let mut destination_node = Node::new(&committer, num_chunks);
+        assert_eq!(destination_node.num_chunks(), num_chunks);
+        assert_eq!(destination_node.chunk_size_scalars(), None);
+        destination_node
+            .receive(source_node.send().unwrap())
+            .unwrap();
+        assert_eq!(destination_node.num_chunks(), num_chunks);
+        assert_eq!(destination_node.chunk_size_scalars(), Some(3));
+        fill_from(&source_node, &mut destination_node);
+        assert_eq!(destination_node.num_chunks(), num_chunks);
+    }
+
+    #[test]
+    fn test_progress() {
+        let num_chunks = 4;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        assert_eq!(source_node.progress(), (num_chunks, num_chunks));
+        let mut destination_node = Node::new(&committer, num_chunks);
+        assert_eq!(destination_node.progress(), (0, num_chunks));
+        assert_eq!(destination_node.fraction_complete(), 0.0);
+        destination_node
+            .receive(message_with_scalars(&source_node, &[1, 0, 0, 0]))
+            .unwrap();
+        assert_eq!(destination_node.progress(), (1, num_chunks));
+        assert_eq!(destination_node.fraction_complete(), 0.25);
+        fill_from(&source_node, &mut destination_node);
+        assert_eq!(destination_node.progress(), (num_chunks, num_chunks));
+        assert_eq!(destination_node.fraction_complete(), 1.0);
+    }
+
+    #[test]
+    fn test_receive_error_display() {
+        assert_eq!(
+            ReceiveError::LinearlyDependentChunk.to_string(),
+            "the chunk is linearly dependent on the received ones"
+        );
+        let error: Box<dyn std::error::Error> = Box::new(
+            ReceiveError::InvalidMessage("Chunk size is too large".to_string()),
+        );
+        assert_eq!(
+            error.to_string(),
+            "invalid message: Chunk size is too large"
+        );
+        assert_eq!(
+            ReceiveError::ExistingCommitmentsMismatch(
+                "The commitments do not match".to_string()
+            )
+            .to_string(),
+            "commitments mismatch: The commitments do not match"
+        );
+    }
+
+    #[test]
+    fn test_new_node() {
+        let committer = Committer::new(2);
+        let node = Node::new(&committer, 3);
+        assert!(!node.is_full());
+        assert_eq!(node.rank(), 0);
+        assert!(node.chunks().is_empty());
+    }
 
     #[test]
     fn test_source_node() {
         let num_chunks = 3;
-        let chunk_size = 4;
-        let committer = Committer::new(chunk_size);
+        let chunk_size: usize = 4;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
         let block = random_u8_slice(num_chunks * chunk_size * 32);
         let source_node =
             Node::new_source(&committer, &block, num_chunks).unwrap();
@@ -272,9 +1852,9 @@ mod tests {
         }
         let committer = measure_time!(
             "gen commiter",
-            // Each scalar represents 252 bits. We add 251 to round up the result, since if we need
+            // Each scalar represents 252 bits. We round up the result, since if we need
             // 1.1 scalars we need 2.
-            Committer::new((chunk_size * 8 + 251) / 252)
+            Committer::new((chunk_size * 8).div_ceil(252))
         );
         let source_node =
             Node::new_source(&committer, &block, num_chunks).unwrap();
@@ -286,13 +1866,13 @@ mod tests {
             Node::new(&committer, num_chunks)
         );
 
+        let mut accepted = 0;
         for _ in 0..num_chunks {
             let message =
                 measure_time!("gen send chunk", source_node.send().unwrap());
             measure_time!(
                 "receive chunk",
-                destination_node
-                    .receive(message)
+                receive_checked(&mut destination_node, message, &mut accepted)
                     .or_else(|e| match e {
                         ReceiveError::LinearlyDependentChunk => Ok(()),
                         _ => Err(e),
@@ -317,56 +1897,419 @@ mod tests {
     }
 
     #[test]
-    fn test_send_receive() {
+    fn test_full_node_stores_no_more_chunks() {
         let num_chunks = 3;
-        let chunk_size = 4;
-        let committer = Committer::new(chunk_size);
+        let chunk_size: usize = 4;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
         let block = random_u8_slice(num_chunks * chunk_size * 32);
         let source_node =
             Node::new_source(&committer, &block, num_chunks).unwrap();
-        let message = source_node.send().unwrap();
         let mut destination_node = Node::new(&committer, num_chunks);
-        destination_node
-            .receive(message)
-            .or_else(|e| match e {
-                ReceiveError::LinearlyDependentChunk => Ok(()),
-                _ => Err(e),
-            })
-            .unwrap();
-        assert_eq!(destination_node.chunks().len(), 1);
-        assert_eq!(destination_node.commitments().len(), num_chunks);
+        while !destination_node.is_full() {
+            destination_node
+                .receive(source_node.send().unwrap())
+                .or_else(|e| match e {
+                    ReceiveError::LinearlyDependentChunk => Ok(()),
+                    _ => Err(e),
+                })
+                .unwrap();
+        }
+        for _ in 0..5 {
+            assert!(matches!(
+                destination_node.receive(source_node.send().unwrap()),
+                Err(ReceiveError::LinearlyDependentChunk)
+            ));
+        }
+        assert_eq!(destination_node.chunks().len(), num_chunks);
+    }
 
-        destination_node.send().unwrap();
+    // Run with `cargo test --release -- --ignored test_high_fanout_receive`.
+    #[test]
+    #[ignore]
+    fn test_high_fanout_receive() {
+        let num_chunks = 128;
+        let chunk_size: usize = 2;
+        let num_messages = 20 * num_chunks;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
+        let block = random_u8_slice(num_chunks * chunk_size * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+        // A relay with half of the rank only forwards dependent messages once
+        // the sink has its chunks.
+        let mut relay_node = Node::new(&committer, num_chunks);
+        while relay_node.chunks().len() < num_chunks / 2 {
+            relay_node.receive(source_node.send().unwrap()).unwrap();
+        }
+
+        let mut destination_node = Node::new(&committer, num_chunks);
+        let mut dependent = 0;
+        let start = std::time::Instant::now();
+        for i in 0..num_messages {
+            // Mostly relayed messages, one out of four from the source.
+            let message = if i % 4 == 0 {
+                source_node.send().unwrap()
+            } else {
+                relay_node.send().unwrap()
+            };
+            match destination_node.receive(message) {
+                Ok(()) => {}
+                Err(ReceiveError::LinearlyDependentChunk) => dependent += 1,
+                Err(e) => panic!("Unexpected error: {:?}", e),
+            }
+            assert!(destination_node.chunks().len() <= num_chunks);
+        }
+        let elapsed = start.elapsed();
+        println!(
+            "received {} messages ({} dependent) in {:?}, {:.0} messages/s",
+            num_messages,
+            dependent,
+            elapsed,
+            num_messages as f64 / elapsed.as_secs_f64()
+        );
+
+        assert!(destination_node.is_full());
+        assert!(dependent > num_messages / 2);
+        assert_eq!(destination_node.chunks().len(), num_chunks);
+        assert_eq!(
+            measure_time!("decode", destination_node.decode()),
+            Ok(block)
+        );
     }
 
     #[test]
-    fn test_decode() {
-        let num_chunks = 3;
-        let chunk_size = 4;
-        let committer = Committer::new(chunk_size);
+    fn test_minimal_forward_set() {
+        let num_chunks = 5;
+        let chunk_size: usize = 2;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
         let block = random_u8_slice(num_chunks * chunk_size * 32);
         let source_node =
             Node::new_source(&committer, &block, num_chunks).unwrap();
-        let message1 = source_node.send().unwrap();
-        let message2 = source_node.send().unwrap();
-        let message3 = source_node.send().unwrap();
+        let mut relay_node = Node::new(&committer, num_chunks);
+        assert!(relay_node.minimal_forward_set().is_empty());
+        fill_from(&source_node, &mut relay_node);
+
+        let forward_set = relay_node.minimal_forward_set();
+        assert_eq!(forward_set.len(), num_chunks);
+        let mut destination_node = Node::new(&committer, num_chunks);
+        let mut accepted = 0;
+        for message in forward_set {
+            receive_checked(&mut destination_node, message, &mut accepted)
+                .unwrap();
+        }
+        assert!(destination_node.is_full());
+        assert_eq!(destination_node.decode().unwrap(), block);
+
+        // A partial relay forwards exactly its rank.
+        let mut partial_node = Node::new(&committer, num_chunks);
+        for message in relay_node.minimal_forward_set().into_iter().take(2) {
+            partial_node.receive(message).unwrap();
+        }
+        let forward_set = partial_node.minimal_forward_set();
+        assert_eq!(forward_set.len(), 2);
+        let mut accepted = 0;
+        let mut destination_node = Node::new(&committer, num_chunks);
+        for message in forward_set {
+            receive_checked(&mut destination_node, message, &mut accepted)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_receive_early_rejection() {
+        let num_chunks = 3;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
         let mut destination_node = Node::new(&committer, num_chunks);
         destination_node
-            .receive(message1)
-            .or_else(|e| match e {
-                ReceiveError::LinearlyDependentChunk => Ok(()),
-                _ => Err(e),
-            })
+            .receive(message_with_scalars(&source_node, &[1, 2, 0]))
             .unwrap();
+
+        // A tampered copy of a dependent message fails the verification.
+        let mut message = message_with_scalars(&source_node, &[2, 4, 0]);
+        message.chunk.data[0] += Scalar::ONE;
+        assert!(matches!(
+            destination_node.receive(message.clone()),
+            Err(ReceiveError::InvalidMessage(_))
+        ));
+        // In trusted dedup mode it is dropped before the verification.
+        destination_node.set_trusted_dedup(true);
+        assert!(matches!(
+            destination_node.receive(message),
+            Err(ReceiveError::LinearlyDependentChunk)
+        ));
+        let mut message = message_with_scalars(&source_node, &[0, 1, 0]);
+        message.chunk.data[0] += Scalar::ONE;
+        assert!(matches!(
+            destination_node.receive(message),
+            Err(ReceiveError::InvalidMessage(_))
+        ));
         destination_node
-            .receive(message2)
-            .or_else(|e| match e {
-                ReceiveError::LinearlyDependentChunk => Ok(()),
-                _ => Err(e),
+            .receive(message_with_scalars(&source_node, &[0, 1, 0]))
+            .unwrap();
+
+        // Structural checks.
+        let mut message = message_with_scalars(&source_node, &[0, 0, 1]);
+        message.commitments.pop();
+        assert!(matches!(
+            destination_node.receive(message),
+            Err(ReceiveError::InvalidMessage(_))
+        ));
+        let mut message = message_with_scalars(&source_node, &[0, 0, 1]);
+        message.chunk.data.push(Scalar::ONE);
+        assert!(matches!(
+            destination_node.receive(message),
+            Err(ReceiveError::InvalidMessage(_))
+        ));
+        assert!(matches!(
+            destination_node
+                .receive(message_with_scalars(&source_node, &[0, 0, 0])),
+            Err(ReceiveError::LinearlyDependentChunk)
+        ));
+    }
+
+    #[test]
+    fn test_verify_sample_rate() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let num_chunks = 4;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        // The coefficients come from a seeded RNG, so the messages are the
+        // same independent ones on every run.
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut tampered = || {
+            let scalars: [u8; 4] = rng.gen();
+            let mut message = message_with_scalars(&source_node, &scalars);
+            message.chunk.data[0] += Scalar::ONE;
+            message
+        };
+
+        let mut destination_node = Node::new(&committer, num_chunks);
+        destination_node.set_verify_sample_rate(0.0);
+        for _ in 0..num_chunks {
+            destination_node.receive(tampered()).unwrap();
+        }
+        assert!(destination_node.is_full());
+
+        let mut destination_node = Node::new(&committer, num_chunks);
+        destination_node.set_verify_sample_rate(1.0);
+        for _ in 0..10 {
+            assert!(matches!(
+                destination_node.receive(tampered()),
+                Err(ReceiveError::InvalidMessage(_))
+            ));
+        }
+        assert!(destination_node.chunks().is_empty());
+
+        // With the same seeded rng, the same messages are verified.
+        let accepted = |seed: u64| {
+            let mut node = Node::new(&committer, num_chunks);
+            node.set_verify_sample_rate(0.5);
+            let mut sampling = StdRng::seed_from_u64(seed);
+            let mut rng = StdRng::seed_from_u64(7);
+            (0..num_chunks)
+                .map(|_| {
+                    let scalars: [u8; 4] = rng.gen();
+                    let mut message =
+                        message_with_scalars(&source_node, &scalars);
+                    message.chunk.data[0] += Scalar::ONE;
+                    node.receive_with_rng(message, &mut sampling).is_ok()
+                })
+                .collect::<Vec<bool>>()
+        };
+        assert_eq!(accepted(1), vec![true, true, false, false]);
+        assert_eq!(accepted(1), accepted(1));
+    }
+
+    #[test]
+    fn test_verify_batch() {
+        let num_chunks = 4;
+        let chunk_size: usize = 2;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * chunk_size * 32),
+            num_chunks,
+        )
+        .unwrap();
+        let mut messages: Vec<Message> =
+            (0..10).map(|_| source_node.send().unwrap()).collect();
+        assert!(source_node.verify_batch(&[]).is_ok());
+        assert!(source_node.verify_batch(&messages).is_ok());
+
+        messages[6].chunk.coefficients[1] += Scalar::ONE;
+        assert!(messages[6].verify(&committer).is_err());
+        assert!(source_node.verify_batch(&messages).is_err());
+        assert!(source_node.verify_batch(&messages[..6]).is_ok());
+
+        messages[6].chunk.coefficients.pop();
+        assert_eq!(
+            source_node.verify_batch(&messages),
+            Err(
+                "Message 6: The number of coefficients and commitments differ"
+                    .to_string()
+            )
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_verify_batch_parallel() {
+        let num_chunks = 4;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        let messages: Vec<Message> = (0..16)
+            .map(|i| {
+                let mut message = source_node.send().unwrap();
+                if i % 3 == 0 {
+                    message.chunk.data[0] += Scalar::ONE;
+                }
+                message
             })
+            .collect();
+        let parallel = source_node.verify_batch_parallel(&messages);
+        let sequential: Vec<Result<(), String>> =
+            messages.iter().map(|m| m.verify(&committer)).collect();
+        assert_eq!(parallel, sequential);
+        assert_eq!(parallel.iter().filter(|r| r.is_err()).count(), 6);
+    }
+
+    #[test]
+    fn test_suggest_num_chunks() {
+        let committer = Committer::new(16);
+        let message_len = |block_len: usize, num_chunks: usize| {
+            let mut block = random_u8_slice(block_len);
+            block.resize(block_len.next_multiple_of(num_chunks * 32), 0);
+            let source_node =
+                Node::new_source(&committer, &block, num_chunks).unwrap();
+            bincode::serialize(&source_node.send().unwrap())
+                .unwrap()
+                .len()
+        };
+        for (block_len, target) in [(1000, 700), (1000, 1200), (4000, 2000)] {
+            let n = suggest_num_chunks(block_len, committer.len(), target);
+            assert!(n > 0);
+            assert!(message_len(block_len, n) <= target);
+            // Fewer chunks either don't fit the committer or the target.
+            let min_chunks =
+                suggest_num_chunks(block_len, committer.len(), usize::MAX);
+            if n > min_chunks {
+                assert!(message_len(block_len, n - 1) > target);
+            }
+        }
+        // The bound is tight: the shortest message fits exactly.
+        let min_chunks = suggest_num_chunks(1000, committer.len(), usize::MAX);
+        let shortest = (min_chunks..=32)
+            .map(|n| message_len(1000, n))
+            .min()
+            .unwrap();
+        assert!(suggest_num_chunks(1000, committer.len(), shortest) > 0);
+        assert_eq!(suggest_num_chunks(1000, committer.len(), shortest - 1), 0);
+        // The chunks need more scalars than the committer has.
+        assert_eq!(suggest_num_chunks(1000, 2, 100_000), 32);
+        assert_eq!(suggest_num_chunks(1000, 16, 100), 0);
+    }
+
+    #[test]
+    fn test_send_interleaved() {
+        let num_chunks = 4;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        assert!(source_node.send_interleaved((0, 0)).is_err());
+
+        let mut systematic = Vec::new();
+        let mut coded = 0;
+        for _ in 0..30 {
+            let message = source_node.send_interleaved((2, 1)).unwrap();
+            assert!(message.verify(&committer).is_ok());
+            match message.is_systematic() {
+                Some(i) => systematic.push(i),
+                None => coded += 1,
+            }
+        }
+        assert_eq!(systematic.len(), 20);
+        assert_eq!(coded, 10);
+        assert_eq!(systematic[..6], [0, 1, 2, 3, 0, 1]);
+
+        // A node with coded chunks only has nothing systematic to send.
+        let mut destination_node = Node::new(&committer, num_chunks);
+        destination_node
+            .receive(source_node.send().unwrap())
             .unwrap();
+        let message = destination_node.send_interleaved((1, 0)).unwrap();
+        assert_eq!(message.is_systematic(), None);
+    }
+
+    #[test]
+    fn test_send_receive() {
+        let num_chunks = 3;
+        let chunk_size: usize = 4;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
+        let block = random_u8_slice(num_chunks * chunk_size * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+        let message = source_node.send().unwrap();
+        let mut destination_node = Node::new(&committer, num_chunks);
         destination_node
-            .receive(message3)
+            .receive(message)
+            .or_else(|e| match e {
+                ReceiveError::LinearlyDependentChunk => Ok(()),
+                _ => Err(e),
+            })
+            .unwrap();
+        assert_eq!(destination_node.chunks().len(), 1);
+        assert_eq!(destination_node.commitments().len(), num_chunks);
+
+        destination_node.send().unwrap();
+    }
+
+    #[test]
+    fn test_decode() {
+        let num_chunks = 3;
+        let chunk_size: usize = 4;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
+        let block = random_u8_slice(num_chunks * chunk_size * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+        let message1 = source_node.send().unwrap();
+        let message2 = source_node.send().unwrap();
+        let message3 = source_node.send().unwrap();
+        let mut destination_node = Node::new(&committer, num_chunks);
+        let mut accepted = 0;
+        receive_checked(&mut destination_node, message1, &mut accepted)
+            .or_else(|e| match e {
+                ReceiveError::LinearlyDependentChunk => Ok(()),
+                _ => Err(e),
+            })
+            .unwrap();
+        receive_checked(&mut destination_node, message2, &mut accepted)
+            .or_else(|e| match e {
+                ReceiveError::LinearlyDependentChunk => Ok(()),
+                _ => Err(e),
+            })
+            .unwrap();
+        receive_checked(&mut destination_node, message3, &mut accepted)
             .or_else(|e| match e {
                 ReceiveError::LinearlyDependentChunk => Ok(()),
                 _ => Err(e),
@@ -377,13 +2320,1064 @@ mod tests {
         assert_eq!(decoded, block);
     }
 
+    // fill_from sends from the source until the destination is full.
+    fn fill_from(source_node: &Node, destination_node: &mut Node) {
+        while !destination_node.is_full() {
+            destination_node
+                .receive(source_node.send().unwrap())
+                .or_else(|e| match e {
+                    ReceiveError::LinearlyDependentChunk => Ok(()),
+                    _ => Err(e),
+                })
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_empty_block() {
+        let num_chunks = 3;
+        let committer = Committer::new(2);
+        let source_node =
+            Node::new_source(&committer, &[], num_chunks).unwrap();
+        let mut destination_node = Node::new(&committer, num_chunks);
+        fill_from(&source_node, &mut destination_node);
+        assert!(destination_node.decode().unwrap().is_empty());
+
+        let source_node =
+            Node::new_source_padded(&committer, &[], num_chunks).unwrap();
+        let mut destination_node = Node::new(&committer, num_chunks);
+        assert!(destination_node.decode().is_err());
+        fill_from(&source_node, &mut destination_node);
+        assert!(destination_node.decode_exact().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_padded_block_len() {
+        let num_chunks = 7;
+        let block = random_u8_slice(1000);
+        // 1008 bytes with the header, padded to 7 chunks of 5 words.
+        let committer = Committer::new(6);
+        let source_node =
+            Node::new_source_padded(&committer, &block, num_chunks).unwrap();
+        assert_eq!(source_node.block_len(), Some(1000));
+        assert_eq!(source_node.decode_exact().unwrap(), block);
+
+        let mut destination_node = Node::new(&committer, num_chunks);
+        destination_node.record_received();
+        fill_from(&source_node, &mut destination_node);
+        assert_eq!(destination_node.block_len(), None);
+        assert_eq!(destination_node.decode_exact().unwrap(), block);
+        destination_node.set_block_len(1000);
+        let replayed =
+            Node::from_replay(&committer, &destination_node.export_replay())
+                .unwrap();
+        assert_eq!(replayed.block_len(), Some(1000));
+        assert_eq!(replayed.decode_exact().unwrap().len(), 1000);
+        destination_node.set_block_len(999);
+        assert!(destination_node.decode_exact().is_err());
+    }
+
+    #[test]
+    fn test_single_byte_block() {
+        let num_chunks = 3;
+        let committer = Committer::new(2);
+        assert!(Node::new_source(&committer, &[0xab], num_chunks).is_err());
+        let source_node =
+            Node::new_source_padded(&committer, &[0xab], num_chunks).unwrap();
+        let mut destination_node = Node::new(&committer, num_chunks);
+        fill_from(&source_node, &mut destination_node);
+        assert_eq!(destination_node.decode_exact().unwrap(), vec![0xab]);
+    }
+
+    #[test]
+    fn test_try_decode() {
+        let num_chunks = 4;
+        let chunk_size: usize = 2;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
+        let block = random_u8_slice(num_chunks * chunk_size * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+        let mut destination_node = Node::new(&committer, num_chunks);
+        assert_eq!(
+            destination_node.try_decode(),
+            Err(DecodeError::Underdetermined {
+                rank: 0,
+                num_chunks
+            })
+        );
+
+        let mut accepted = 0;
+        while !destination_node.is_full() {
+            let message = source_node.send().unwrap();
+            let _ =
+                receive_checked(&mut destination_node, message, &mut accepted);
+            if accepted < num_chunks {
+                assert_eq!(
+                    destination_node.try_decode(),
+                    Err(DecodeError::Underdetermined {
+                        rank: accepted,
+                        num_chunks
+                    })
+                );
+            }
+        }
+        assert_eq!(destination_node.try_decode().unwrap(), block);
+    }
+
+    #[test]
+    fn test_coefficient_stats() {
+        let num_chunks = 3;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        let mut destination_node = Node::new(&committer, num_chunks);
+        assert_eq!(destination_node.coefficient_stats().max_magnitude, 0.0);
+
+        let rows: [&[u8]; 2] = [&[3, 0, 7], &[1, 250, 2]];
+        for row in rows {
+            destination_node
+                .receive(message_with_scalars(&source_node, row))
+                .unwrap();
+        }
+        let stats = destination_node.coefficient_stats();
+        assert_eq!(stats.max_magnitude, 250.0);
+        assert_eq!(stats.mean_magnitude, 263.0 / 6.0);
+
+        // Relaying compounds the coefficients.
+        let mut relayed_node = Node::new(&committer, num_chunks);
+        relayed_node
+            .receive(message_with_scalars(&destination_node, &[200, 200]))
+            .unwrap();
+        let stats = relayed_node.coefficient_stats();
+        assert_eq!(stats.max_magnitude, 50000.0);
+        assert_eq!(stats.mean_magnitude, (800.0 + 50000.0 + 1800.0) / 3.0);
+    }
+
+    #[test]
+    fn test_decode_blocked() {
+        let num_chunks = 3;
+        // Three groups of 64 scalars, the last one partial.
+        let chunk_size: usize = 150;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
+        let block = random_u8_slice(num_chunks * chunk_size * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+        let mut destination_node = Node::new(&committer, num_chunks);
+        fill_from(&source_node, &mut destination_node);
+        let decoded = destination_node.decode().unwrap();
+        assert_eq!(decoded, block);
+        for block_width in [0, 1, 64, 100, 128, 1000] {
+            let mut out = Vec::new();
+            destination_node
+                .decode_blocked(block_width, &mut out)
+                .unwrap();
+            assert_eq!(out, decoded);
+        }
+        let mut out = Vec::new();
+        assert!(Node::new(&committer, num_chunks)
+            .decode_blocked(64, &mut out)
+            .is_err());
+    }
+
+    #[test]
+    fn test_decode_partial() {
+        let num_chunks = 3;
+        let chunk_size: usize = 2;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
+        let block = random_u8_slice(num_chunks * chunk_size * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+        let source_chunk = |i: usize| {
+            block[i * chunk_size * 32..(i + 1) * chunk_size * 32].to_vec()
+        };
+
+        let mut destination_node = Node::new(&committer, num_chunks);
+        assert!(destination_node.decode_partial().is_empty());
+        destination_node
+            .receive(message_with_scalars(&source_node, &[2, 3, 0]))
+            .unwrap();
+        assert!(destination_node.decode_partial().is_empty());
+        destination_node
+            .receive(message_with_scalars(&source_node, &[0, 5, 0]))
+            .unwrap();
+        assert_eq!(
+            destination_node.decode_partial(),
+            vec![(0, source_chunk(0)), (1, source_chunk(1))]
+        );
+
+        // Only the first chunk is in the span of these two.
+        let mut destination_node = Node::new(&committer, num_chunks);
+        for row in [[4, 1, 1], [1, 1, 1]] {
+            destination_node
+                .receive(message_with_scalars(&source_node, &row))
+                .unwrap();
+        }
+        assert_eq!(
+            destination_node.decode_partial(),
+            vec![(0, source_chunk(0))]
+        );
+
+        fill_from(&source_node, &mut destination_node);
+        assert_eq!(
+            destination_node.decode_partial(),
+            destination_node.decode_chunks().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_inverse_cache() {
+        let num_chunks = 3;
+        let committer = Committer::new(2);
+        let block = random_u8_slice(num_chunks * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+
+        let mut destination_node = Node::new(&committer, num_chunks);
+        destination_node
+            .receive(message_with_scalars(&source_node, &[1, 2, 3]))
+            .unwrap();
+        // Nothing is cached while the node can't decode.
+        assert!(destination_node.decode().is_err());
+        assert!(!destination_node.has_cached_inverse());
+        fill_from(&source_node, &mut destination_node);
+        assert!(!destination_node.has_cached_inverse());
+
+        let decoded = destination_node.decode().unwrap();
+        assert_eq!(decoded, block);
+        assert!(destination_node.has_cached_inverse());
+        assert_eq!(destination_node.decode().unwrap(), decoded);
+        let chunks: Vec<Vec<u8>> = destination_node
+            .decode_chunks()
+            .unwrap()
+            .into_iter()
+            .map(|(_, chunk)| chunk)
+            .collect();
+        assert_eq!(chunks.concat(), decoded);
+
+        // A full node stores no further row, so the cache stays valid.
+        assert!(matches!(
+            destination_node.receive(source_node.send().unwrap()),
+            Err(ReceiveError::LinearlyDependentChunk)
+        ));
+        assert!(destination_node.has_cached_inverse());
+        assert_eq!(destination_node.decode().unwrap(), decoded);
+
+        // A restored node computes its own inverse.
+        let restored =
+            Node::from_bytes(&committer, &destination_node.to_bytes()).unwrap();
+        assert!(!restored.has_cached_inverse());
+        assert_eq!(restored.decode().unwrap(), decoded);
+    }
+
+    #[test]
+    fn test_decode_to() {
+        let num_chunks = 4;
+        let chunk_size: usize = 2;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
+        let block = random_u8_slice(num_chunks * chunk_size * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+        let mut destination_node = Node::new(&committer, num_chunks);
+        let mut out = Vec::new();
+        assert!(destination_node.decode_to(&mut out).is_err());
+        assert!(out.is_empty());
+
+        fill_from(&source_node, &mut destination_node);
+        destination_node.decode_to(&mut out).unwrap();
+        assert_eq!(out, destination_node.decode().unwrap());
+        assert_eq!(out, block);
+    }
+
+    #[test]
+    fn test_decode_range() {
+        let num_chunks = 4;
+        let chunk_size: usize = 2;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
+        let block = random_u8_slice(num_chunks * chunk_size * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+        let mut destination_node = Node::new(&committer, num_chunks);
+        fill_from(&source_node, &mut destination_node);
+        let decoded = destination_node.decode().unwrap();
+
+        // Within a chunk, across boundaries, whole block and empty.
+        for (start, end) in [(3, 40), (60, 70), (50, 200), (0, 256), (64, 64)] {
+            assert_eq!(
+                destination_node.decode_range(start, end).unwrap(),
+                decoded[start..end]
+            );
+        }
+        assert!(destination_node.decode_range(10, 5).is_err());
+        assert!(destination_node.decode_range(0, 257).is_err());
+        assert!(Node::new(&committer, num_chunks)
+            .decode_range(0, 1)
+            .is_err());
+    }
+
+    #[test]
+    fn test_replay() {
+        let num_chunks = 4;
+        let chunk_size: usize = 2;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
+        let block = random_u8_slice(num_chunks * chunk_size * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+        let mut destination_node = Node::new(&committer, num_chunks);
+        // Not recorded.
+        destination_node
+            .receive(source_node.send().unwrap())
+            .unwrap();
+        destination_node.record_received();
+        let mut tampered = source_node.send().unwrap();
+        tampered.chunk.data[0] += Scalar::ONE;
+        assert!(destination_node.receive(tampered).is_err());
+        for _ in 0..2 {
+            destination_node
+                .receive(source_node.send().unwrap())
+                .unwrap();
+        }
+
+        // The replay misses the first message.
+        let replayed =
+            Node::from_replay(&committer, &destination_node.export_replay())
+                .unwrap();
+        assert_eq!(replayed.chunks().len(), 2);
+        assert_eq!(replayed.export_replay(), destination_node.export_replay());
+
+        let mut destination_node = Node::new(&committer, num_chunks);
+        destination_node.record_received();
+        fill_from(&source_node, &mut destination_node);
+        let replayed =
+            Node::from_replay(&committer, &destination_node.export_replay())
+                .unwrap();
+        assert!(replayed.is_full());
+        assert_eq!(replayed.chunks(), destination_node.chunks());
+        assert_eq!(replayed.decode().unwrap(), block);
+        assert!(Node::from_replay(&committer, &[1, 2, 3]).is_err());
+
+        // The settings are restored, and the hop limit applies to the replay.
+        let mut destination_node = Node::new(&committer, num_chunks);
+        destination_node.set_verify_sample_rate(0.5);
+        destination_node.set_hop_limit(Some(2));
+        destination_node.set_u32_coefficients(true);
+        destination_node.record_received();
+        let mut far = source_node.send().unwrap();
+        far.hops = 3;
+        assert!(destination_node.receive(far).is_err());
+        destination_node
+            .receive(source_node.send().unwrap())
+            .unwrap();
+        let replayed =
+            Node::from_replay(&committer, &destination_node.export_replay())
+                .unwrap();
+        assert_eq!(replayed.chunks().len(), 1);
+        assert_eq!(replayed.to_bytes(), destination_node.to_bytes());
+    }
+
+    #[test]
+    fn test_eta_rounds() {
+        let num_chunks = 4;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        assert_eq!(source_node.eta_rounds(1.0), 0.0);
+        assert_eq!(source_node.eta_rounds(0.0), 0.0);
+
+        let mut destination_node = Node::new(&committer, num_chunks);
+        destination_node
+            .receive(source_node.send().unwrap())
+            .unwrap();
+        assert_eq!(destination_node.eta_rounds(1.0), 3.0);
+        assert_eq!(destination_node.eta_rounds(0.5), 6.0);
+        assert_eq!(destination_node.eta_rounds(3.0), 1.0);
+        assert_eq!(destination_node.eta_rounds(0.0), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_subspace_relation() {
+        let num_chunks = 3;
+        let chunk_size: usize = 4;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
+        let block = random_u8_slice(num_chunks * chunk_size * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+        let node_with = |scalars: &[&[u8]]| {
+            let mut node = Node::new(&committer, num_chunks);
+            for s in scalars {
+                node.receive(message_with_scalars(&source_node, s)).unwrap();
+            }
+            node
+        };
+
+        let a = node_with(&[&[1, 0, 0], &[0, 1, 0]]);
+        let equal = node_with(&[&[1, 1, 0], &[1, 2, 0]]);
+        let subset = node_with(&[&[3, 2, 0]]);
+        let overlapping = node_with(&[&[1, 0, 0], &[0, 0, 1]]);
+        let disjoint = node_with(&[&[0, 0, 1]]);
+
+        assert_eq!(
+            a.subspace_relation(&equal).unwrap(),
+            SubspaceRelation::Equal
+        );
+        assert_eq!(
+            subset.subspace_relation(&a).unwrap(),
+            SubspaceRelation::Subset
+        );
+        assert_eq!(
+            a.subspace_relation(&subset).unwrap(),
+            SubspaceRelation::Superset
+        );
+        assert_eq!(
+            a.subspace_relation(&overlapping).unwrap(),
+            SubspaceRelation::Overlapping
+        );
+        assert_eq!(
+            a.subspace_relation(&disjoint).unwrap(),
+            SubspaceRelation::Disjoint
+        );
+        assert_eq!(
+            source_node.subspace_relation(&a).unwrap(),
+            SubspaceRelation::Superset
+        );
+
+        let other_block = random_u8_slice(num_chunks * chunk_size * 32);
+        let other_source =
+            Node::new_source(&committer, &other_block, num_chunks).unwrap();
+        assert!(a.subspace_relation(&other_source).is_err());
+
+        // The rows of nodes on different windows are not comparable.
+        let windowed = Node::new_windowed(&committer, num_chunks, 1);
+        assert_eq!(
+            a.subspace_relation(&windowed).err(),
+            Some("The column offsets are different".to_string())
+        );
+        assert!(a.subspace_relation(&Node::new(&committer, 2)).is_err());
+    }
+
+    #[test]
+    fn test_windowed_decode() {
+        let num_chunks = 5;
+        let chunk_size: usize = 4;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
+        let block = random_u8_slice(num_chunks * chunk_size * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+        let mut windowed = Node::new_windowed(&committer, 3, 2);
+
+        assert!(matches!(
+            windowed
+                .receive(message_with_scalars(&source_node, &[1, 0, 1, 0, 0])),
+            Err(ReceiveError::InvalidMessage(_))
+        ));
+        windowed
+            .receive(message_with_scalars(&source_node, &[0, 0, 1, 2, 3]))
+            .unwrap();
+        windowed
+            .receive(message_with_scalars(&source_node, &[0, 0, 4, 0, 7]))
+            .unwrap();
+
+        // Messages sent by a window node carry the whole generation's
+        // coefficients and are valid for other nodes on the same window.
+        let mut relayed = Node::new_windowed(&committer, 3, 2);
+        relayed.receive(windowed.send().unwrap()).unwrap();
+
+        windowed
+            .receive(message_with_scalars(&source_node, &[0, 0, 0, 5, 1]))
+            .unwrap();
+        assert!(windowed.is_full());
+
+        let chunk_len = chunk_size * 32;
+        for (index, chunk) in windowed.decode_chunks().unwrap() {
+            assert_eq!(
+                chunk,
+                &block[index * chunk_len..(index + 1) * chunk_len]
+            );
+        }
+        assert_eq!(windowed.decode().unwrap(), &block[2 * chunk_len..]);
+    }
+
+    #[test]
+    fn test_concatenated_generations() {
+        let num_chunks = 3;
+        let first_committer = Committer::new(2);
+        let second_committer = Committer::new(5);
+        let committer = first_committer.concat(&second_committer);
+        assert_eq!(committer.len(), 7);
+        let first = Node::new_source(
+            &first_committer,
+            &random_u8_slice(96),
+            num_chunks,
+        )
+        .unwrap();
+        let second = Node::new_source(
+            &second_committer,
+            &random_u8_slice(3 * 128),
+            num_chunks,
+        )
+        .unwrap();
+
+        let chunks: Vec<Vec<Scalar>> = first
+            .chunks()
+            .iter()
+            .zip(second.chunks())
+            .map(|(a, b)| first_committer.concat_chunk(a, b).unwrap())
+            .collect();
+        assert!(first_committer
+            .concat_chunk(&second.chunks()[0], &first.chunks()[0])
+            .is_err());
+
+        let commitments =
+            concat_commitments(first.commitments(), second.commitments())
+                .unwrap();
+        let mut destination_node =
+            Node::new_with_commitments(&committer, commitments.clone())
+                .unwrap();
+
+        let coefficients: Vec<Scalar> =
+            (1..=num_chunks as u64).map(Scalar::from).collect();
+        let data: Vec<Scalar> = (0..chunks[0].len())
+            .map(|k| {
+                (0..num_chunks)
+                    .map(|i| coefficients[i] * chunks[i][k])
+                    .sum()
+            })
+            .collect();
+        let mut message =
+            Message::new(Chunk { data, coefficients }, commitments);
+        assert!(message.verify(&committer).is_ok());
+        assert!(message.verify(&first_committer).is_err());
+        destination_node.receive(message.clone()).unwrap();
+
+        message.chunk.data[0] += Scalar::ONE;
+        assert!(message.verify(&committer).is_err());
+        assert!(Node::new_with_commitments(&committer, vec![]).is_err());
+        assert!(concat_commitments(
+            first.commitments(),
+            &second.commitments()[1..]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_is_systematic() {
+        let num_chunks = 3;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        for i in 0..num_chunks {
+            let message = source_node.send_interleaved((1, 0)).unwrap();
+            assert_eq!(message.is_systematic(), Some(i));
+        }
+        assert_eq!(
+            message_with_scalars(&source_node, &[0, 2, 0]).is_systematic(),
+            None
+        );
+        assert_eq!(
+            message_with_scalars(&source_node, &[1, 1, 0]).is_systematic(),
+            None
+        );
+        assert_eq!(
+            message_with_scalars(&source_node, &[0, 0, 0]).is_systematic(),
+            None
+        );
+        assert_eq!(
+            source_node
+                .send_interleaved((0, 1))
+                .unwrap()
+                .is_systematic(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_is_dependent_on() {
+        let message = |coefficients: &[u64]| {
+            Message::new(
+                Chunk {
+                    data: vec![],
+                    coefficients: coefficients
+                        .iter()
+                        .map(|&c| Scalar::from(c))
+                        .collect(),
+                },
+                vec![],
+            )
+        };
+        let held = vec![message(&[1, 2, 0]), message(&[0, 1, 1])];
+        assert!(message(&[5, 10, 0]).is_dependent_on(&held[..1]));
+        assert!(message(&[2, 7, 3]).is_dependent_on(&held));
+        assert!(!message(&[0, 0, 1]).is_dependent_on(&held));
+        assert!(!message(&[1, 0, 0]).is_dependent_on(&[]));
+        assert!(message(&[0, 0, 0]).is_dependent_on(&[]));
+        assert!(!message(&[1, 2]).is_dependent_on(&held));
+    }
+
+    #[test]
+    fn test_serialized_len() {
+        let committer = Committer::new(3);
+        for num_chunks in [1, 4, 9] {
+            let source_node = Node::new_source(
+                &committer,
+                &random_u8_slice(num_chunks * 64),
+                num_chunks,
+            )
+            .unwrap();
+            let message = source_node.send().unwrap();
+            assert_eq!(
+                message.serialized_len(),
+                bincode::serialize(&message).unwrap().len()
+            );
+        }
+        let empty = Message::new(
+            Chunk {
+                data: vec![],
+                coefficients: vec![],
+            },
+            vec![],
+        );
+        assert_eq!(
+            empty.serialized_len(),
+            bincode::serialize(&empty).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_overhead_ratio() {
+        let committer = Committer::new(4);
+        let mut previous = 0.0;
+        for num_chunks in [1, 2, 8, 32] {
+            let source_node = Node::new_source(
+                &committer,
+                &random_u8_slice(num_chunks * 96),
+                num_chunks,
+            )
+            .unwrap();
+            let message = source_node.send().unwrap();
+            let ratio = message.overhead_ratio();
+            // 4 data scalars, one coefficient and commitment per chunk, the
+            // vector lengths and the hops.
+            let expected = (28 + 64 * num_chunks) as f64 / 128.0;
+            assert!((ratio - expected).abs() < 1e-9);
+            assert!(ratio > previous);
+            previous = ratio;
+        }
+    }
+
+    #[test]
+    fn test_verify_mismatched_lengths() {
+        let num_chunks = 3;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        let mut message = source_node.send().unwrap();
+        message.commitments.pop();
+        assert_eq!(
+            message.verify(&committer),
+            Err("The number of coefficients and commitments differ".to_string())
+        );
+        message.chunk.coefficients.truncate(1);
+        assert!(message.verify(&committer).is_err());
+
+        let mut node = Node::new(&committer, num_chunks);
+        assert!(matches!(
+            node.receive(message),
+            Err(ReceiveError::InvalidMessage(_))
+        ));
+        assert_eq!(node.rank(), 0);
+    }
+
+    #[test]
+    fn test_recode_multi_hop() {
+        let num_chunks = 4;
+        let committer = Committer::new(2);
+        let block = random_u8_slice(num_chunks * 32);
+        let source_node =
+            Node::new_source(&committer, &block, num_chunks).unwrap();
+        let mut relay = Node::new(&committer, num_chunks);
+        let mut sink = Node::new(&committer, num_chunks);
+        assert!(relay.recode().is_err());
+
+        // The relay forwards at partial rank, the sink can't go past it.
+        while relay.rank() < 2 {
+            let _ = relay.receive(source_node.send().unwrap());
+        }
+        for _ in 0..10 {
+            let message = relay.recode().unwrap();
+            assert!(message.verify(&committer).is_ok());
+            let _ = sink.receive(message);
+        }
+        assert_eq!(sink.rank(), 2);
+
+        while !relay.is_full() {
+            let _ = relay.receive(source_node.send().unwrap());
+        }
+        while !sink.is_full() {
+            let _ = sink.receive(relay.recode().unwrap());
+        }
+        assert_eq!(sink.decode().unwrap(), block);
+    }
+
+    #[test]
+    fn test_hop_limit() {
+        let num_chunks = 2;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        assert_eq!(source_node.send().unwrap().hops(), 1);
+
+        // Each relay forwards the chunks one hop further.
+        let mut relays = vec![source_node];
+        for hops in 1..4 {
+            let mut relay = Node::new(&committer, num_chunks);
+            while !relay.is_full() {
+                let message = relays.last().unwrap().recode().unwrap();
+                assert_eq!(message.hops(), hops);
+                let _ = relay.receive(message);
+            }
+            relays.push(relay);
+        }
+
+        let mut sink = Node::new(&committer, num_chunks);
+        sink.set_hop_limit(Some(2));
+        assert!(sink.receive(relays[1].send().unwrap()).is_ok());
+        assert!(matches!(
+            sink.receive(relays[2].send().unwrap()),
+            Err(ReceiveError::HopLimitExceeded)
+        ));
+        assert_eq!(sink.rank(), 1);
+        let restored = Node::from_bytes(&committer, &sink.to_bytes()).unwrap();
+        assert_eq!(restored.hop_limit, Some(2));
+        assert_eq!(restored.send().unwrap().hops(), 3);
+    }
+
+    #[test]
+    fn test_u32_coefficients() {
+        let num_chunks = 2;
+        let committer = Committer::new(2);
+        let mut relay = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        relay.set_u32_coefficients(true);
+        // Relay the largest combinations until they no longer fit.
+        let mut hops = 0;
+        let overflow = loop {
+            let mut next = Node::new(&committer, num_chunks);
+            next.set_u32_coefficients(true);
+            let messages = [
+                relay.send_with_coeffs(&[255, 255]),
+                relay.send_with_coeffs(&[255, 254]),
+            ];
+            match messages {
+                [Ok(a), Ok(b)] => {
+                    next.receive(a).unwrap();
+                    next.receive(b).unwrap();
+                }
+                [Err(e), _] | [_, Err(e)] => break e,
+            }
+            relay = next;
+            hops += 1;
+        };
+        assert!(overflow.contains("overflows a u32"), "{}", overflow);
+        assert!((2..6).contains(&hops), "{}", hops);
+
+        // The Scalar path carries on.
+        relay.set_u32_coefficients(false);
+        assert!(relay.send_with_coeffs(&[255, 255]).is_ok());
+        assert!(relay.send().is_ok());
+    }
+
+    #[test]
+    fn test_verify_against_hash() {
+        let num_chunks = 3;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        let message = source_node.send().unwrap();
+        let root = message.commitments_hash();
+        assert!(message.verify_against_hash(&committer, &root).is_ok());
+
+        let mut swapped = message.clone();
+        swapped.commitments.swap(0, 1);
+        assert_eq!(
+            swapped.verify_against_hash(&committer, &root),
+            Err("The commitments hash does not match".to_string())
+        );
+
+        let mut tampered = message.clone();
+        tampered.chunk.data[0] += Scalar::ONE;
+        assert_eq!(
+            tampered.verify_against_hash(&committer, &root),
+            Err("The commitment does not match".to_string())
+        );
+    }
+
+    #[test]
+    fn test_commitments_hash_cache() {
+        // The cache does not prevent sharing a node between threads.
+        fn assert_sync<T: Sync>() {}
+        assert_sync::<Node>();
+
+        let num_chunks = 3;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        let mut node = Node::new(&committer, num_chunks);
+        node.receive(source_node.send().unwrap()).unwrap();
+        assert_eq!(node.commitments_hash.get(), None);
+        // The hash is computed on the first message checked against the
+        // commitments and must then match them.
+
+        let message = source_node.send().unwrap();
+        let mut tampered = message.clone();
+        tampered.commitments[2] = tampered.commitments[0];
+        assert!(matches!(
+            node.receive(tampered),
+            Err(ReceiveError::ExistingCommitmentsMismatch(_))
+        ));
+        assert_eq!(
+            node.commitments_hash.get(),
+            Some(&message.commitments_hash())
+        );
+
+        assert!(node.receive(message).is_ok());
+        let mut tampered = source_node.send().unwrap();
+        tampered.commitments.pop();
+        assert!(matches!(
+            node.receive(tampered),
+            Err(ReceiveError::InvalidMessage(_))
+        ));
+        assert_eq!(node.rank(), 2);
+    }
+
+    #[test]
+    fn test_receive_unchecked() {
+        let num_chunks = 3;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        let mut message = source_node.send().unwrap();
+        message.chunk.data[0] += Scalar::ONE;
+        assert!(message.verify(&committer).is_err());
+
+        let mut node = Node::new(&committer, num_chunks);
+        assert!(matches!(
+            node.receive(message.clone()),
+            Err(ReceiveError::InvalidMessage(_))
+        ));
+        assert!(node.receive_unchecked(message.clone()).is_ok());
+        assert_eq!(node.rank(), 1);
+        assert!(matches!(
+            node.receive_unchecked(message),
+            Err(ReceiveError::LinearlyDependentChunk)
+        ));
+    }
+
+    #[test]
+    fn test_verify_vartime() {
+        let num_chunks = 4;
+        let committer = Committer::new(3);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 64),
+            num_chunks,
+        )
+        .unwrap();
+        let mut node = Node::new(&committer, num_chunks);
+        for i in 0..12 {
+            let mut message = source_node.send().unwrap();
+            match i % 3 {
+                0 => message.chunk.data[1] += Scalar::ONE,
+                1 => message.chunk.coefficients[0] += Scalar::ONE,
+                _ => {}
+            }
+            assert_eq!(
+                message.verify(&committer),
+                message.verify_vartime(&committer)
+            );
+            let result = node.receive_vartime(message);
+            if i % 3 == 2 {
+                assert!(!matches!(
+                    result,
+                    Err(ReceiveError::InvalidMessage(_))
+                ));
+            } else {
+                assert!(matches!(result, Err(ReceiveError::InvalidMessage(_))));
+            }
+        }
+        let mut message = source_node.send().unwrap();
+        message.commitments.pop();
+        assert_eq!(
+            message.verify(&committer),
+            message.verify_vartime(&committer)
+        );
+    }
+
+    #[test]
+    fn test_new_source_errors() {
+        let committer = Committer::new(2);
+        // Two words per chunk need three scalars.
+        assert_eq!(
+            Node::new_source(&committer, &random_u8_slice(4 * 64), 4).err(),
+            Some("Chunk 0 is too large. Expected 2, got 3".to_string())
+        );
+        assert_eq!(
+            Node::new_source(&committer, &random_u8_slice(4 * 48), 4).err(),
+            Some("Chunk size 48 is not divisible by 32".to_string())
+        );
+        assert!(
+            Node::new_source(&committer, &random_u8_slice(4 * 32), 4).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_audit_node() {
+        let num_chunks = 3;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        let mut audit_node = AuditNode::new(&committer);
+        for i in 0..20 {
+            let mut message = source_node.send().unwrap();
+            if i % 4 == 0 {
+                message.chunk.data[0] += Scalar::ONE;
+            }
+            assert_eq!(audit_node.audit(&message), i % 4 != 0);
+        }
+        assert_eq!(audit_node.valid(), 15);
+        assert_eq!(audit_node.invalid(), 5);
+        // Only the counters, whatever the number of messages.
+        assert_eq!(
+            std::mem::size_of::<AuditNode>(),
+            3 * std::mem::size_of::<usize>()
+        );
+    }
+
+    #[test]
+    fn test_chunk_debug() {
+        let chunk = Chunk {
+            data: vec![Scalar::ONE; 3],
+            coefficients: vec![Scalar::from(3u32), Scalar::ZERO],
+        };
+        assert_eq!(
+            format!("{:?}", chunk),
+            "Chunk { data: [3 scalars], coefficients: [3, 0] }"
+        );
+        let chunk = Chunk {
+            data: vec![],
+            coefficients: vec![-Scalar::ONE],
+        };
+        assert!(format!("{:?}", chunk).contains("Scalar"));
+    }
+
+    #[test]
+    fn test_from_header_and_chunk() {
+        let num_chunks = 3;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        let header = source_node.send().unwrap().header();
+        assert_eq!(header.commitments(), source_node.commitments());
+
+        let message = source_node.send().unwrap();
+        let mut rebuilt = Message::from_header_and_chunk(
+            &header,
+            message.chunk.data.clone(),
+            message.chunk.coefficients.clone(),
+        )
+        .unwrap();
+        assert_eq!(rebuilt.hops(), 0);
+        assert!(rebuilt.verify(&committer).is_ok());
+        rebuilt.set_hops(message.hops());
+        assert_eq!(
+            bincode::serialize(&rebuilt).unwrap(),
+            bincode::serialize(&message).unwrap()
+        );
+
+        let mut data = message.chunk.data.clone();
+        data[0] += Scalar::ONE;
+        let rebuilt = Message::from_header_and_chunk(
+            &header,
+            data,
+            message.chunk.coefficients.clone(),
+        )
+        .unwrap();
+        assert!(rebuilt.verify(&committer).is_err());
+        assert!(Message::from_header_and_chunk(
+            &header,
+            message.chunk.data.clone(),
+            message.chunk.coefficients[1..].to_vec(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_bad_point_encoding() {
+        let num_chunks = 3;
+        let committer = Committer::new(2);
+        let source_node = Node::new_source(
+            &committer,
+            &random_u8_slice(num_chunks * 32),
+            num_chunks,
+        )
+        .unwrap();
+        let mut bytes =
+            bincode::serialize(&source_node.send().unwrap()).unwrap();
+        assert!(bincode::deserialize::<Message>(&bytes).is_ok());
+        // The last commitment is serialized right before the 4-byte hops.
+        let len = bytes.len();
+        bytes[len - 36..len - 4].fill(0xff);
+        assert!(bincode::deserialize::<Message>(&bytes).is_err());
+    }
+
     #[test]
     fn test_message_serialization() {
-        use super::Message;
         // Setup
         let num_chunks = 3;
-        let chunk_size = 4;
-        let committer = Committer::new(chunk_size);
+        let chunk_size: usize = 4;
+        let committer = Committer::new((chunk_size * 32 * 8).div_ceil(252));
         let block = random_u8_slice(num_chunks * chunk_size * 32);
 
         // Create a source node and get a message